@@ -0,0 +1,23 @@
+fn main() {
+    #[cfg(feature = "ffi")]
+    header();
+}
+
+/// Generates the C header of the `ffi` module into `OUT_DIR`. The copy in
+/// `include/` is checked in, and the `ffi` test fails if it is out of date.
+#[cfg(feature = "ffi")]
+fn header() {
+    const SOURCES: [&str; 3] = ["src/ffi.rs", "src/status.rs", "src/env.rs"];
+    for path in SOURCES.iter().chain(&["cbindgen.toml"]) {
+        println!("cargo:rerun-if-changed={path}");
+    }
+    let mut builder = cbindgen::Builder::new()
+        .with_config(cbindgen::Config::from_file("cbindgen.toml").expect("invalid cbindgen.toml"));
+    for path in SOURCES {
+        builder = builder.with_src(path);
+    }
+    builder
+        .generate()
+        .expect("failed to generate the C header")
+        .write_to_file(std::path::Path::new(&std::env::var("OUT_DIR").unwrap()).join("poc_jit.h"));
+}
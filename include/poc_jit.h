@@ -0,0 +1,239 @@
+/* Generated by cbindgen from src/ffi.rs; do not edit. */
+
+#ifndef POC_JIT_H
+#define POC_JIT_H
+
+#include <stdarg.h>
+#include <stdbool.h>
+#include <stddef.h>
+#include <stdint.h>
+#include <stdlib.h>
+
+/**
+ * Why a run halted. JIT code returns the discriminant as a raw `u64`.
+ */
+enum PocStatus
+#if defined(__cplusplus) || __STDC_VERSION__ >= 202311L
+  : uint64_t
+#endif // defined(__cplusplus) || __STDC_VERSION__ >= 202311L
+ {
+  /**
+   * STOP, RETURN or the end of the code was reached.
+   */
+  POC_STATUS_STOPPED = 0,
+  /**
+   * CALLSUB nested deeper than `RETURN_STACK_LIMIT`.
+   */
+  POC_STATUS_RETURN_STACK_OVERFLOW = 1,
+  /**
+   * RETSUB with an empty return stack.
+   */
+  POC_STATUS_RETURN_STACK_UNDERFLOW = 2,
+  /**
+   * CALLSUB to a target that is not a BEGINSUB.
+   */
+  POC_STATUS_INVALID_JUMP = 3,
+  /**
+   * BEGINSUB reached by falling through instead of through CALLSUB.
+   */
+  POC_STATUS_WALK_INTO_SUBROUTINE = 4,
+  /**
+   * The gas limit was exceeded.
+   */
+  POC_STATUS_OUT_OF_GAS = 5,
+  /**
+   * An instruction would have grown the operand stack past the configured
+   * limit. Consumes all gas, like `OutOfGas`.
+   */
+  POC_STATUS_STACK_OVERFLOW = 6,
+  /**
+   * An instruction needed more operands than the stack holds, under
+   * `UnderflowPolicy::Trap`. Consumes all gas, like `OutOfGas`.
+   */
+  POC_STATUS_STACK_UNDERFLOW = 7,
+  /**
+   * JIT code run under `Host::run_guarded` faulted, in this frame or in
+   * a program it called. Consumes all gas. The interpreter never halts
+   * with this.
+   */
+  POC_STATUS_TRAPPED = 8,
+  /**
+   * JIT code was handed fewer storage slots than the largest key it
+   * uses. Nothing runs and no gas is consumed. The interpreter never
+   * halts with this.
+   */
+  POC_STATUS_STORAGE_TOO_SMALL = 9,
+};
+#ifndef __cplusplus
+#if __STDC_VERSION__ >= 202311L
+typedef enum PocStatus PocStatus;
+#else
+typedef uint64_t PocStatus;
+#endif // __STDC_VERSION__ >= 202311L
+#endif // __cplusplus
+
+/**
+ * Both engines, sharing one config.
+ */
+typedef struct PocEngine PocEngine;
+
+/**
+ * Loaded bytecode, compiled or not.
+ */
+typedef struct PocProgram PocProgram;
+
+/**
+ * Limits an engine enforces; see `Config`.
+ */
+typedef struct PocEngineConfig {
+  /**
+   * At most 65536 words.
+   */
+  size_t stack_limit;
+  /**
+   * Halt with `StackUnderflow` instead of reading missing operands as
+   * zero.
+   */
+  bool trap_underflow;
+} PocEngineConfig;
+
+/**
+ * Transaction and block context visible to a program. Compiled code reads
+ * the fields directly, hence the fixed layout.
+ */
+typedef struct PocEnvironment {
+  /**
+   * Id of the program (or account) that started this call.
+   */
+  uint64_t caller;
+  /**
+   * Id of the running program. CALL sets it to the callee's program id.
+   */
+  uint64_t address;
+  /**
+   * Value sent along with the call; always 0 for nested CALLs.
+   */
+  uint64_t callvalue;
+  uint64_t number;
+  uint64_t timestamp;
+  uint64_t chain_id;
+} PocEnvironment;
+
+/**
+ * Storage of the program being executed. Either callback may be null:
+ * keys then read as zero, or writes are dropped.
+ */
+typedef struct PocStorageCallbacks {
+  void *user_data;
+  uint64_t (*load)(void *user_data, uint8_t key);
+  void (*store)(void *user_data, uint8_t key, uint64_t value);
+} PocStorageCallbacks;
+
+typedef struct PocExecResult {
+  PocStatus status;
+  uint64_t gas_used;
+} PocExecResult;
+
+#ifdef __cplusplus
+extern "C" {
+#endif // __cplusplus
+
+/**
+ * Creates an engine, with the default config if `config` is null. Returns
+ * null if the config's stack limit is above 65536. Free the engine with
+ * `poc_engine_free`.
+ *
+ * # Safety
+ *
+ * `config` must be null or point to a valid `PocEngineConfig`.
+ */
+struct PocEngine *poc_engine_new(const struct PocEngineConfig *config);
+
+/**
+ * # Safety
+ *
+ * `engine` must be null or come from `poc_engine_new`, and not be used
+ * afterwards.
+ */
+void poc_engine_free(struct PocEngine *engine);
+
+/**
+ * Loads raw bytecode, which is validated and copied. Returns null if it has
+ * an undefined opcode or a truncated immediate, or if `code` is null and
+ * `len` is not 0. Free the program with `poc_program_free`.
+ *
+ * # Safety
+ *
+ * `code` must point to `len` readable bytes.
+ */
+struct PocProgram *poc_program_load(const uint8_t *code, size_t len);
+
+/**
+ * Loads a container, which is validated and copied. Returns null if it is
+ * invalid.
+ *
+ * # Safety
+ *
+ * `bytes` must point to `len` readable bytes.
+ */
+struct PocProgram *poc_program_load_container(const uint8_t *bytes, size_t len);
+
+/**
+ * Compiles `program` with the config of `engine`; from then on it runs as
+ * machine code, on engines with the same config only. Returns false if an
+ * argument is null.
+ *
+ * # Safety
+ *
+ * `engine` and `program` must be null or valid.
+ */
+bool poc_program_compile(const struct PocEngine *engine, struct PocProgram *program);
+
+/**
+ * # Safety
+ *
+ * `program` must be null or come from `poc_program_load` or
+ * `poc_program_load_container`, and not be used afterwards.
+ */
+void poc_program_free(struct PocProgram *program);
+
+/**
+ * Runs `program` on `engine` and stores how it ended in `result`. `env`
+ * and `storage` may be null for a default environment and zeroed storage.
+ * A fault in compiled code halts it with `Trapped`. Returns false, without
+ * running anything, if `engine`, `program` or `result` is null, `calldata`
+ * is null with a non-zero length, or the program was compiled with a
+ * different config.
+ *
+ * # Safety
+ *
+ * Every pointer must be null or valid, `calldata` spanning `calldata_len`
+ * bytes, and the callbacks must be safe to call with `user_data`.
+ */
+bool poc_execute(struct PocEngine *engine,
+                 const struct PocProgram *program,
+                 const uint8_t *calldata,
+                 size_t calldata_len,
+                 uint64_t gas_limit,
+                 const struct PocEnvironment *env,
+                 const struct PocStorageCallbacks *storage,
+                 struct PocExecResult *result);
+
+/**
+ * Output of the last run on `engine`, set by RETURN, valid until the next
+ * run or until the engine is freed. Its length is stored in `len`. Returns
+ * null with a length of 0 if `engine` is null, and does nothing if `len` is
+ * null.
+ *
+ * # Safety
+ *
+ * `engine` and `len` must be null or valid, `len` pointing to a writable
+ * `size_t`.
+ */
+const uint8_t *poc_engine_return_data(const struct PocEngine *engine, size_t *len);
+
+#ifdef __cplusplus
+}  // extern "C"
+#endif  // __cplusplus
+
+#endif  /* POC_JIT_H */
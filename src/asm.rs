@@ -0,0 +1,304 @@
+//! Text format for programs, as read by [`assemble`] and written by
+//! [`disassemble`] and [`disassemble_container`].
+//!
+//! ```text
+//! # loads slot 0, doubles it in a subroutine and stores it back
+//!         SLOAD 0
+//!         CALLSUB double
+//!         SSTORE 0x00
+//!         STOP
+//! .section
+//! double: BEGINSUB
+//!         DUP1
+//!         ADD
+//!         RETSUB
+//! .data 0x0102030405060708
+//! .stack 4
+//! ```
+//!
+//! A line holds an optional `label:`, then an instruction or a directive,
+//! then an optional `#` comment. Mnemonics are those of
+//! [`instructions::mnemonic`], in any case. Immediates are decimal, `0x`
+//! hexadecimal or a label, which stands for its offset in the code image.
+//! `.byte` emits a raw byte. `.section`, `.data` (hex bytes, appended to the
+//! data section) and `.stack` (the max stack height, [`MAX_STACK_HEIGHT`] by
+//! default) make the output a [`Container`]: `.section` ends the current code
+//! section and starts the next.
+
+use crate::container::{Container, MAX_STACK_HEIGHT};
+use crate::instructions::{self, opcodes::*};
+use std::collections::HashMap;
+use std::fmt;
+use std::ops::Range;
+
+/// Why a program text was rejected, with the 1-based line it is on.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum AsmError {
+    UnknownMnemonic {
+        line: usize,
+        name: String,
+    },
+    UnknownDirective {
+        line: usize,
+        name: String,
+    },
+    /// An operand that is missing, malformed, out of range or not expected.
+    InvalidOperand {
+        line: usize,
+        text: String,
+    },
+    UndefinedLabel {
+        line: usize,
+        label: String,
+    },
+    DuplicateLabel {
+        line: usize,
+        label: String,
+    },
+}
+
+impl fmt::Display for AsmError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            AsmError::UnknownMnemonic { line, name } => {
+                write!(f, "line {line}: unknown instruction {name}")
+            }
+            AsmError::UnknownDirective { line, name } => {
+                write!(f, "line {line}: unknown directive {name}")
+            }
+            AsmError::InvalidOperand { line, text } => {
+                write!(f, "line {line}: invalid operand `{text}`")
+            }
+            AsmError::UndefinedLabel { line, label } => {
+                write!(f, "line {line}: undefined label {label}")
+            }
+            AsmError::DuplicateLabel { line, label } => {
+                write!(f, "line {line}: label {label} is already defined")
+            }
+        }
+    }
+}
+
+impl std::error::Error for AsmError {}
+
+/// Assembles a program text into bytecode, or into an encoded container if
+/// it uses a container directive. The container is not validated.
+pub fn assemble(text: &str) -> Result<Vec<u8>, AsmError> {
+    let mut code = Vec::new();
+    let mut sections = vec![0];
+    let mut data = Vec::new();
+    let mut max_stack_height = None;
+    let mut labels = HashMap::new();
+    // immediates naming a label: offset in `code`, width, line, label
+    let mut fixups = Vec::new();
+
+    for (i, line) in text.lines().enumerate() {
+        let line_no = i + 1;
+        let mut rest = line.split('#').next().unwrap().trim();
+        if let Some((label, after)) = rest.split_once(':') {
+            let label = label.trim();
+            if !is_label(label) {
+                return Err(AsmError::InvalidOperand {
+                    line: line_no,
+                    text: label.to_string(),
+                });
+            }
+            if labels.insert(label.to_string(), code.len()).is_some() {
+                return Err(AsmError::DuplicateLabel {
+                    line: line_no,
+                    label: label.to_string(),
+                });
+            }
+            rest = after.trim();
+        }
+        let mut words = rest.split_whitespace();
+        let Some(name) = words.next() else {
+            continue;
+        };
+        let operand = words.next();
+        if let Some(extra) = words.next() {
+            return Err(AsmError::InvalidOperand {
+                line: line_no,
+                text: extra.to_string(),
+            });
+        }
+        let invalid = |text: &str| AsmError::InvalidOperand {
+            line: line_no,
+            text: text.to_string(),
+        };
+
+        if let Some(directive) = name.strip_prefix('.') {
+            match (directive, operand) {
+                ("byte", Some(text)) => {
+                    let byte = parse_number(text)
+                        .filter(|&n| n <= 0xFF)
+                        .ok_or_else(|| invalid(text))?;
+                    code.push(byte as u8);
+                }
+                ("section", None) => sections.push(code.len()),
+                ("data", Some(text)) => data.extend(parse_hex(text).ok_or_else(|| invalid(text))?),
+                ("stack", Some(text)) => {
+                    let height = parse_number(text)
+                        .and_then(|n| u16::try_from(n).ok())
+                        .ok_or_else(|| invalid(text))?;
+                    max_stack_height = Some(height);
+                }
+                ("byte" | "data" | "stack", None) | ("section", Some(_)) => {
+                    return Err(invalid(operand.unwrap_or(name)));
+                }
+                _ => {
+                    return Err(AsmError::UnknownDirective {
+                        line: line_no,
+                        name: name.to_string(),
+                    });
+                }
+            }
+            continue;
+        }
+
+        let op = opcode(name).ok_or_else(|| AsmError::UnknownMnemonic {
+            line: line_no,
+            name: name.to_string(),
+        })?;
+        code.push(op);
+        let width = instructions::immediate_len(op);
+        match (width, operand) {
+            (0, None) => {}
+            (_, Some(text)) if width > 0 => {
+                if let Some(value) = parse_number(text) {
+                    if width < 8 && value >> (8 * width) != 0 {
+                        return Err(invalid(text));
+                    }
+                    code.extend(&value.to_be_bytes()[8 - width..]);
+                } else if is_label(text) {
+                    fixups.push((code.len(), width, line_no, text.to_string()));
+                    code.extend(std::iter::repeat_n(0, width));
+                } else {
+                    return Err(invalid(text));
+                }
+            }
+            _ => return Err(invalid(operand.unwrap_or(name))),
+        }
+    }
+
+    for (at, width, line, label) in fixups {
+        let target = *labels.get(&label).ok_or_else(|| AsmError::UndefinedLabel {
+            line,
+            label: label.clone(),
+        })?;
+        if width < 8 && target >> (8 * width) != 0 {
+            return Err(AsmError::InvalidOperand { line, text: label });
+        }
+        code[at..at + width].copy_from_slice(&(target as u64).to_be_bytes()[8 - width..]);
+    }
+
+    if sections.len() == 1 && data.is_empty() && max_stack_height.is_none() {
+        return Ok(code);
+    }
+    sections.push(code.len());
+    let sections: Vec<&[u8]> = sections.windows(2).map(|w| &code[w[0]..w[1]]).collect();
+    Ok(Container::encode(
+        &sections,
+        &data,
+        max_stack_height.unwrap_or(MAX_STACK_HEIGHT),
+    ))
+}
+
+/// Program text for `code`, one instruction per line with its pc in a
+/// comment. CALLSUB targets get labels; undefined opcodes and an immediate
+/// cut short by the end of the code are written as `.byte`.
+pub fn disassemble(code: &[u8]) -> String {
+    let mut text = String::new();
+    write_code(&mut text, code, std::slice::from_ref(&(0..code.len())));
+    text
+}
+
+/// Program text for a container, which assembles back to the same bytes.
+pub fn disassemble_container(container: &Container) -> String {
+    let mut text = String::new();
+    write_code(&mut text, container.code(), container.sections());
+    if !container.data().is_empty() {
+        text += &format!(".data 0x{}\n", hex(container.data()));
+    }
+    text += &format!(".stack {}\n", container.max_stack_height());
+    text
+}
+
+fn write_code(text: &mut String, code: &[u8], sections: &[Range<usize>]) {
+    // only subroutine entries get labels, so every label is defined
+    let entries = instructions::subroutine_entries(code);
+    let is_target = |target: usize| entries.get(target).copied().unwrap_or(false);
+
+    for (i, section) in sections.iter().enumerate() {
+        if i > 0 {
+            text.push_str(".section\n");
+        }
+        let mut pc = section.start;
+        while pc < section.end {
+            let op = code[pc];
+            let end = pc + 1 + instructions::immediate_len(op);
+            let prefix = if is_target(pc) {
+                format!("{}:", label(pc))
+            } else {
+                String::new()
+            };
+            let (instruction, next) = match instructions::mnemonic(op) {
+                Some(name) if end <= section.end => {
+                    let immediate = &code[pc + 1..end];
+                    let target = immediate
+                        .try_into()
+                        .map(|bytes| u16::from_be_bytes(bytes) as usize);
+                    let instruction = match target {
+                        Ok(target) if op == CALLSUB && is_target(target) => {
+                            format!("{name} {}", label(target))
+                        }
+                        _ if immediate.is_empty() => name,
+                        _ => format!("{name} 0x{}", hex(immediate)),
+                    };
+                    (instruction, end)
+                }
+                _ => (format!(".byte 0x{op:02x}"), pc + 1),
+            };
+            let line = format!("{prefix:<10}{instruction}");
+            text.push_str(&format!("{line:<32}# {pc}\n"));
+            pc = next;
+        }
+    }
+}
+
+fn label(pc: usize) -> String {
+    format!("sub_{pc}")
+}
+
+fn opcode(name: &str) -> Option<u8> {
+    (0..=u8::MAX).find(|&op| {
+        instructions::mnemonic(op).is_some_and(|mnemonic| mnemonic.eq_ignore_ascii_case(name))
+    })
+}
+
+fn is_label(text: &str) -> bool {
+    text.starts_with(|c: char| c.is_ascii_alphabetic() || c == '_')
+        && text.chars().all(|c| c.is_ascii_alphanumeric() || c == '_')
+}
+
+fn parse_number(text: &str) -> Option<u64> {
+    match text.strip_prefix("0x") {
+        Some(digits) => u64::from_str_radix(digits, 16).ok(),
+        None => text.parse().ok(),
+    }
+}
+
+fn parse_hex(text: &str) -> Option<Vec<u8>> {
+    let digits = text.strip_prefix("0x")?;
+    if digits.len() % 2 != 0 {
+        return None;
+    }
+    (0..digits.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(&digits[i..i + 2], 16).ok())
+        .collect()
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{b:02x}")).collect()
+}
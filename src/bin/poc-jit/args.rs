@@ -0,0 +1,63 @@
+//! Options of a subcommand: `--name value` options and `--name` switches,
+//! in any order around its positional arguments.
+
+use std::io;
+use std::str::FromStr;
+
+pub struct Args {
+    args: Vec<String>,
+}
+
+impl Args {
+    pub fn new(args: Vec<String>) -> Self {
+        Self { args }
+    }
+
+    /// Removes the switch `--name` and returns whether it was given.
+    pub fn flag(&mut self, name: &str) -> bool {
+        let len = self.args.len();
+        self.args.retain(|arg| arg.strip_prefix("--") != Some(name));
+        self.args.len() != len
+    }
+
+    /// Removes `--name <value>` and parses the value; the last one wins if
+    /// the option is repeated.
+    pub fn option<T: FromStr>(&mut self, name: &str) -> io::Result<Option<T>> {
+        let mut value = None;
+        while let Some(i) = self
+            .args
+            .iter()
+            .position(|arg| arg.strip_prefix("--") == Some(name))
+        {
+            if i + 1 == self.args.len() {
+                return Err(usage_error(format!("--{name} needs a value")));
+            }
+            value = Some(self.args.remove(i + 1));
+            self.args.remove(i);
+        }
+        value
+            .map(|value| {
+                value
+                    .parse()
+                    .map_err(|_| usage_error(format!("invalid value `{value}` for --{name}")))
+            })
+            .transpose()
+    }
+
+    /// The positional arguments, once every option has been taken; there
+    /// must be exactly `N`.
+    pub fn finish<const N: usize>(self) -> io::Result<[String; N]> {
+        if let Some(arg) = self.args.iter().find(|arg| arg.starts_with("--")) {
+            return Err(usage_error(format!("unknown option {arg}")));
+        }
+        let count = self.args.len();
+        self.args
+            .try_into()
+            .map_err(|_| usage_error(format!("expected {N} argument(s), got {count}")))
+    }
+}
+
+/// An error in the command line, reported along with the usage.
+pub fn usage_error(message: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidInput, message)
+}
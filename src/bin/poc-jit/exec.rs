@@ -0,0 +1,210 @@
+//! Running a program on either engine and capturing what it left behind,
+//! so runs can be printed and compared.
+
+use crate::hex;
+use poc_jit::{CompiledProgram, Config, Container, Context, Environment, Log, Status, Trap, VM};
+use std::fmt::Write as _;
+use std::str::FromStr;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Engine {
+    Interpreter,
+    Jit,
+}
+
+impl FromStr for Engine {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, ()> {
+        match s {
+            "interp" | "interpreter" => Ok(Engine::Interpreter),
+            "jit" => Ok(Engine::Jit),
+            _ => Err(()),
+        }
+    }
+}
+
+pub enum Program {
+    Code(Vec<u8>),
+    Container(Container),
+}
+
+/// A program with its inputs and the programs it can CALL, deployed in
+/// order from id 0.
+pub struct Run<'a> {
+    pub program: &'a Program,
+    pub deployed: &'a [&'a [u8]],
+    pub calldata: &'a [u8],
+    pub env: Environment,
+    pub gas_limit: u64,
+    pub config: Config,
+}
+
+/// State left by a run. Storage and memory keep their non-zero entries.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Final {
+    pub status: Status,
+    /// The fault that halted compiled code with `Trapped`.
+    pub trap: Option<Trap>,
+    pub gas_used: u64,
+    pub stack: Vec<u64>,
+    pub storage: Vec<(usize, u64)>,
+    pub memory: Vec<(usize, u8)>,
+    pub return_data: Vec<u8>,
+    pub logs: Vec<Log>,
+    /// Storage of each deployed program.
+    pub deployed: Vec<Vec<(usize, u64)>>,
+}
+
+impl Run<'_> {
+    pub fn on(&self, engine: Engine) -> Final {
+        match engine {
+            Engine::Interpreter => self.interpret(),
+            Engine::Jit => self.compile().1,
+        }
+    }
+
+    pub fn interpret(&self) -> Final {
+        let mut vm = VM::default();
+        vm.set_gas_limit(self.gas_limit);
+        vm.set_config(self.config);
+        for code in self.deployed {
+            vm.deploy(code.to_vec())
+                .expect("deployed programs are validated when loaded");
+        }
+        let status = match self.program {
+            Program::Code(code) => vm.interpret(code, self.calldata, &self.env),
+            Program::Container(container) => {
+                vm.interpret_container(container, self.calldata, &self.env)
+            }
+        };
+        Final {
+            status,
+            trap: None,
+            gas_used: vm.gas_used(),
+            stack: vm.stack().to_vec(),
+            storage: sorted(vm.storage()),
+            memory: mem_snapshot(vm.memory()),
+            return_data: vm.return_data().to_vec(),
+            logs: vm.logs().to_vec(),
+            deployed: (0..self.deployed.len() as u64)
+                .map(|id| sorted(vm.program_storage(id)))
+                .collect(),
+        }
+    }
+
+    /// Compiles the program and runs it, recovering from faults.
+    pub fn compile(&self) -> (CompiledProgram, Final) {
+        let program = match self.program {
+            Program::Code(code) => CompiledProgram::new(code, &self.config)
+                .expect("programs are validated when loaded"),
+            Program::Container(container) => {
+                CompiledProgram::from_container(container, &self.config)
+            }
+        };
+        let mut ctx = Context::new(self.config);
+        for code in self.deployed {
+            ctx.host_mut()
+                .deploy(code)
+                .expect("deployed programs are validated when loaded");
+        }
+        ctx.calldata = self.calldata.to_vec();
+        ctx.env = self.env;
+        ctx.gas_limit = self.gas_limit;
+        let (status, trap, gas_used) = match program.run_guarded(&mut ctx) {
+            Ok(outcome) => (outcome.status, None, outcome.gas_used),
+            Err(trap) => (Status::Trapped, Some(trap), self.gas_limit),
+        };
+        let host = ctx.host();
+        let last = Final {
+            status,
+            trap,
+            gas_used,
+            stack: host.stack(),
+            storage: mem_snapshot(ctx.storage()),
+            memory: mem_snapshot(ctx.memory()),
+            return_data: host.return_data().to_vec(),
+            logs: host.logs().to_vec(),
+            deployed: (0..self.deployed.len() as u64)
+                .map(|id| mem_snapshot(host.storage(id)))
+                .collect(),
+        };
+        (program, last)
+    }
+}
+
+impl Final {
+    /// Every field with its value, in the same order for every run.
+    pub fn fields(&self) -> Vec<(&'static str, String)> {
+        let status = match &self.trap {
+            Some(trap) => format!("{:?} ({trap})", self.status),
+            None => format!("{:?}", self.status),
+        };
+        let mut fields = vec![
+            ("status", status),
+            ("gas used", self.gas_used.to_string()),
+            ("stack", format!("{:?}", self.stack)),
+            ("storage", format!("{:?}", self.storage)),
+            ("memory", format!("{:?}", self.memory)),
+            ("return data", format!("0x{}", hex(&self.return_data))),
+        ];
+        let mut logs = String::new();
+        for log in &self.logs {
+            let _ = write!(logs, "[topics {:?}, data 0x{}]", log.topics, hex(&log.data));
+        }
+        fields.push((
+            "logs",
+            format!("{} {logs}", self.logs.len()).trim_end().to_string(),
+        ));
+        for (id, storage) in self.deployed.iter().enumerate() {
+            fields.push(("deployed storage", format!("{id}: {storage:?}")));
+        }
+        fields
+    }
+
+    /// Names of the fields `other` differs in. The stack and memory only
+    /// count if both runs stopped: they are scratch space of the run, which
+    /// a caller never sees once it fails, and how far a failed run got
+    /// before halting is unspecified. Everything else is observable after
+    /// any run, so it always counts.
+    pub fn diff(&self, other: &Final) -> Vec<&'static str> {
+        let stopped = self.status == Status::Stopped && other.status == Status::Stopped;
+        [
+            ("status", self.status != other.status),
+            ("gas used", self.gas_used != other.gas_used),
+            ("stack", stopped && self.stack != other.stack),
+            ("storage", self.storage != other.storage),
+            ("memory", stopped && self.memory != other.memory),
+            ("return data", self.return_data != other.return_data),
+            ("logs", self.logs != other.logs),
+            ("deployed storage", self.deployed != other.deployed),
+        ]
+        .into_iter()
+        .filter_map(|(name, differs)| differs.then_some(name))
+        .collect()
+    }
+}
+
+pub fn mem_snapshot<T: Copy + Default + PartialEq>(mem: &[T]) -> Vec<(usize, T)> {
+    mem.iter()
+        .enumerate()
+        .filter_map(|(i, &v)| {
+            if v != T::default() {
+                Some((i, v))
+            } else {
+                None
+            }
+        })
+        .collect()
+}
+
+/// Non-zero slots of interpreter storage, by key.
+pub fn sorted(storage: &std::collections::HashMap<u8, u64>) -> Vec<(usize, u64)> {
+    let mut slots: Vec<(usize, u64)> = storage
+        .iter()
+        .filter(|&(_, &v)| v != 0)
+        .map(|(&k, &v)| (k as usize, v))
+        .collect();
+    slots.sort();
+    slots
+}
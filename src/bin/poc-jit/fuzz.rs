@@ -0,0 +1,101 @@
+//! Differential fuzzing: random programs run on both engines, which must
+//! end in the same state.
+
+use crate::args::{Args, usage_error};
+use crate::exec::{Engine, Program, Run};
+use crate::hex;
+use crate::random::{CALLEE, Case, Weights, random_case};
+use poc_jit::{Config, UnderflowPolicy};
+use rand::{Rng, SeedableRng};
+use std::process::ExitCode;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+const GAS_LIMIT: u64 = 10_000_000;
+
+/// `fuzz [--seed <n>] [--cases <n>] [--len <n>]`. Case `i` is generated
+/// from seed `seed + i` alone, so `--seed <seed + i> --cases 1` replays it.
+/// Exits with a failure if any case ends differently on the two engines.
+pub fn fuzz(mut args: Args) -> std::io::Result<ExitCode> {
+    let seed: u64 = match args.option("seed")? {
+        Some(seed) => seed,
+        None => SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap()
+            .as_secs(),
+    };
+    let cases: u64 = args.option("cases")?.unwrap_or(1000);
+    let len: usize = args.option("len")?.unwrap_or(100);
+    let [] = args.finish()?;
+    if len == 0 {
+        return Err(usage_error("--len must be at least 1".to_string()));
+    }
+
+    println!("fuzzing {cases} programs of {len} instructions from seed {seed}");
+    let weights = Weights::default();
+    let mut failures = 0;
+    for case in 0..cases {
+        let case_seed = seed.wrapping_add(case);
+        let mut rng = rand::rngs::StdRng::seed_from_u64(case_seed);
+        let Case {
+            code,
+            container,
+            calldata,
+            env,
+        } = random_case(&mut rng, len, &weights);
+        // a quarter of the cases run out of gas part way, and a quarter trap
+        // on stack underflow
+        let gas_limit = if rng.gen_ratio(1, 4) {
+            rng.gen_range(0..5_000)
+        } else {
+            GAS_LIMIT
+        };
+        let config = Config {
+            underflow: if rng.gen_ratio(1, 4) {
+                UnderflowPolicy::Trap
+            } else {
+                UnderflowPolicy::ZeroFill
+            },
+            ..Config::default()
+        };
+        let program = Program::Container(container);
+        let run = Run {
+            program: &program,
+            deployed: &[CALLEE],
+            calldata: &calldata,
+            env,
+            gas_limit,
+            config,
+        };
+        let interpreted = run.on(Engine::Interpreter);
+        let compiled = run.on(Engine::Jit);
+        let diff = interpreted.diff(&compiled);
+        if diff.is_empty() {
+            continue;
+        }
+
+        failures += 1;
+        println!();
+        println!(
+            "case {case} (seed {case_seed}) differs in {}",
+            diff.join(", ")
+        );
+        println!("  code: {}", hex(&code));
+        println!("  calldata: {}", hex(&calldata));
+        println!("  gas limit: {gas_limit}, config: {config:?}");
+        for ((name, interpreted), (_, compiled)) in
+            interpreted.fields().into_iter().zip(compiled.fields())
+        {
+            if diff.contains(&name) {
+                println!("  {name}: interpreter {interpreted}, JIT {compiled}");
+            }
+        }
+    }
+
+    if failures > 0 {
+        println!();
+        println!("{failures} of {cases} cases differ");
+        return Ok(ExitCode::FAILURE);
+    }
+    println!("all {cases} cases agree");
+    Ok(ExitCode::SUCCESS)
+}
@@ -0,0 +1,316 @@
+//! Command-line front end to both engines: runs, compares and fuzzes them,
+//! benchmarks the JIT against the interpreter, and converts programs between
+//! hex and the text format of `poc_jit::asm`.
+
+mod args;
+mod bench;
+mod exec;
+mod fuzz;
+mod random;
+
+use args::{Args, usage_error};
+use exec::{Engine, Program, Run};
+use poc_jit::config::MAX_STACK_LIMIT;
+use poc_jit::container::{MAGIC, validate_bytecode};
+use poc_jit::{
+    CompiledProgram, Config, Container, Environment, UnderflowPolicy, asm, evm, instructions,
+};
+use std::path::Path;
+use std::process::ExitCode;
+
+const USAGE: &str = "\
+usage: poc-jit <command> [options]
+
+commands:
+  run <file>      run a program and print the state it ends in
+  compare <file>  run a program on both engines and diff the results
+  bench           benchmark the JIT against the interpreter on random programs
+  fuzz            run random programs on both engines until they disagree
+  asm <file>      assemble program text into hex
+  disasm <file>   disassemble a program into text
+
+A program file is program text if it ends in .asm, and hex otherwise, of
+bytecode or of a container.
+
+options of run, compare and disasm:
+  --evm                 the file is hex of standard EVM bytecode
+options of run and compare:
+  --engine interp|jit   engine of run (default: jit)
+  --gas <n>             gas limit (default: 10000000)
+  --calldata <hex>      call data (default: none)
+  --stack-limit <n>     operand stack limit, at most 65536 (default: 1024)
+  --trap-underflow      halt on stack underflow instead of reading zeros
+options of disasm:
+  --native              list the machine code of each instruction
+options of bench, which override the config:
+  --config <file>       config file, TOML or .json (default: the built-in one)
+  --seed <n>            seed of the random inputs (default: the time)
+  --repetitions <n>     timed runs of each case per engine (default: 1)
+  --weights <OP=n,...>  weights of generated instructions (default: all 1)
+  --sizes <name,...>    sizes to run, of small, medium, large, xlarge, xxlarge
+                        or those of the config
+  --cases <n>           cases of each size
+options of fuzz:
+  --seed <n>            seed of the first case (default: the time)
+  --cases <n>           number of cases (default: 1000)
+  --len <n>             instructions per program (default: 100)
+";
+
+const GAS_LIMIT: u64 = 10_000_000;
+
+fn main() -> ExitCode {
+    let mut args = std::env::args().skip(1);
+    let command = args.next();
+    let args = Args::new(args.collect());
+    let result = match command.as_deref() {
+        Some("run") => run(args),
+        Some("compare") => compare(args),
+        Some("bench") => bench::bench(args),
+        Some("fuzz") => fuzz::fuzz(args),
+        Some("asm") => assemble(args),
+        Some("disasm") => disassemble(args),
+        Some("help" | "--help" | "-h") => {
+            print!("{USAGE}");
+            return ExitCode::SUCCESS;
+        }
+        Some(command) => Err(usage_error(format!("unknown command {command}"))),
+        None => Err(usage_error("no command given".to_string())),
+    };
+    match result {
+        Ok(code) => code,
+        Err(err) if err.kind() == std::io::ErrorKind::InvalidInput => {
+            eprintln!("poc-jit: {err}\n\n{USAGE}");
+            ExitCode::from(2)
+        }
+        Err(err) => {
+            eprintln!("poc-jit: {err}");
+            ExitCode::FAILURE
+        }
+    }
+}
+
+/// `run <file>`: runs the program on one engine and prints its final state.
+fn run(mut args: Args) -> std::io::Result<ExitCode> {
+    let engine: Engine = args.option("engine")?.unwrap_or(Engine::Jit);
+    let inputs = Inputs::parse(&mut args)?;
+    let [path] = args.finish()?;
+    let program = load_runnable(Path::new(&path), inputs.evm)?;
+    let last = inputs.run(&program).on(engine);
+    for (name, value) in last.fields() {
+        println!("{:<18}{value}", format!("{name}:"));
+    }
+    Ok(ExitCode::SUCCESS)
+}
+
+/// `compare <file>`: runs the program on both engines and prints the fields
+/// they differ in. Exits with a failure if there are any.
+fn compare(mut args: Args) -> std::io::Result<ExitCode> {
+    let inputs = Inputs::parse(&mut args)?;
+    let [path] = args.finish()?;
+    let program = load_runnable(Path::new(&path), inputs.evm)?;
+    let run = inputs.run(&program);
+    let interpreted = run.on(Engine::Interpreter);
+    let compiled = run.on(Engine::Jit);
+    let diff = interpreted.diff(&compiled);
+    for ((name, interpreted), (_, compiled)) in
+        interpreted.fields().into_iter().zip(compiled.fields())
+    {
+        if diff.contains(&name) {
+            println!("{:<18}interpreter {interpreted}", format!("{name}:"));
+            println!("{:<18}JIT         {compiled}", "");
+        } else {
+            println!("{:<18}{interpreted}", format!("{name}:"));
+        }
+    }
+    if diff.is_empty() {
+        println!("\nthe engines agree");
+        Ok(ExitCode::SUCCESS)
+    } else {
+        println!("\nthe engines differ in {}", diff.join(", "));
+        Ok(ExitCode::FAILURE)
+    }
+}
+
+/// `asm <file>`: prints the program text in the file as hex.
+fn assemble(args: Args) -> std::io::Result<ExitCode> {
+    let [path] = args.finish()?;
+    let text = std::fs::read_to_string(&path).map_err(|err| in_file(Path::new(&path), err))?;
+    let bytes = asm::assemble(&text).map_err(|err| invalid_data(format!("{path}: {err}")))?;
+    println!("{}", hex(&bytes));
+    Ok(ExitCode::SUCCESS)
+}
+
+/// `disasm <file>`: prints the program as text, or with `--native` every
+/// instruction with the machine code the JIT emits for it.
+fn disassemble(mut args: Args) -> std::io::Result<ExitCode> {
+    let evm = args.flag("evm");
+    let native = args.flag("native");
+    let [path] = args.finish()?;
+    // the JIT only compiles valid code
+    let program = if native {
+        load_runnable(Path::new(&path), evm)?
+    } else {
+        load_program(Path::new(&path), evm)?
+    };
+    match (&program, native) {
+        (Program::Code(code), false) => print!("{}", asm::disassemble(code)),
+        (Program::Container(container), false) => {
+            print!("{}", asm::disassemble_container(container))
+        }
+        (Program::Code(code), true) => {
+            let compiled =
+                CompiledProgram::new(code, &Config::default()).expect("validated by load_runnable");
+            dump_native(&path, code, &compiled);
+        }
+        (Program::Container(container), true) => {
+            let compiled = CompiledProgram::from_container(container, &Config::default());
+            dump_native(&path, container.code(), &compiled);
+        }
+    }
+    Ok(ExitCode::SUCCESS)
+}
+
+fn dump_native(path: &str, code: &[u8], program: &CompiledProgram) {
+    println!(
+        "{path}: {} instructions, {} bytes of native code",
+        program.map().pcs().len(),
+        program.code_size()
+    );
+    println!("{:>6}  {:<16}  native code", "pc", "instruction");
+    for (pc, native) in program.map().instructions() {
+        let op = code[pc];
+        let immediate = &code[pc + 1..pc + 1 + instructions::immediate_len(op)];
+        let mut instruction = instructions::mnemonic(op).unwrap_or_else(|| format!("0x{op:02x}"));
+        if !immediate.is_empty() {
+            instruction += &format!(" 0x{}", hex(immediate));
+        }
+        let bytes: Vec<String> = program.code()[native]
+            .iter()
+            .map(|b| format!("{b:02x}"))
+            .collect();
+        println!("{pc:>6}  {instruction:<16}  {}", bytes.join(" "));
+    }
+}
+
+/// Inputs of `run` and `compare`.
+struct Inputs {
+    evm: bool,
+    gas_limit: u64,
+    calldata: Vec<u8>,
+    config: Config,
+}
+
+impl Inputs {
+    fn parse(args: &mut Args) -> std::io::Result<Self> {
+        let calldata: Option<String> = args.option("calldata")?;
+        let calldata = match calldata {
+            Some(text) => {
+                parse_hex(&text).ok_or_else(|| usage_error(format!("invalid call data {text}")))?
+            }
+            None => Vec::new(),
+        };
+        let stack_limit = args
+            .option("stack-limit")?
+            .unwrap_or(Config::default().stack_limit);
+        if stack_limit > MAX_STACK_LIMIT {
+            return Err(usage_error(format!(
+                "--stack-limit must be at most {MAX_STACK_LIMIT}"
+            )));
+        }
+        let config = Config {
+            stack_limit,
+            underflow: if args.flag("trap-underflow") {
+                UnderflowPolicy::Trap
+            } else {
+                UnderflowPolicy::ZeroFill
+            },
+        };
+        Ok(Self {
+            evm: args.flag("evm"),
+            gas_limit: args.option("gas")?.unwrap_or(GAS_LIMIT),
+            calldata,
+            config,
+        })
+    }
+
+    fn run<'a>(&'a self, program: &'a Program) -> Run<'a> {
+        Run {
+            program,
+            deployed: &[],
+            calldata: &self.calldata,
+            env: Environment::default(),
+            gas_limit: self.gas_limit,
+            config: self.config,
+        }
+    }
+}
+
+/// Reads a program file: program text if it ends in `.asm`, hex otherwise.
+/// With `evm`, the hex is EVM bytecode, translated by [`evm::decode`].
+/// Bytes starting with the container magic are parsed as a container.
+fn load_program(path: &Path, evm: bool) -> std::io::Result<Program> {
+    let fail = |message: String| invalid_data(format!("{}: {message}", path.display()));
+    let bytes = if path.extension().is_some_and(|ext| ext == "asm") {
+        if evm {
+            return Err(usage_error("--evm needs a hex file".to_string()));
+        }
+        let text = std::fs::read_to_string(path).map_err(|err| in_file(path, err))?;
+        asm::assemble(&text).map_err(|err| fail(err.to_string()))?
+    } else if evm {
+        evm::decode(&read_hex(path)?).map_err(|err| fail(err.to_string()))?
+    } else {
+        read_hex(path)?
+    };
+    if bytes.starts_with(&MAGIC) {
+        let container = Container::parse(&bytes).map_err(|err| fail(err.to_string()))?;
+        Ok(Program::Container(container))
+    } else {
+        Ok(Program::Code(bytes))
+    }
+}
+
+/// Like [`load_program`], but bytecode must also pass
+/// [`validate_bytecode`], as the engines only run valid code.
+fn load_runnable(path: &Path, evm: bool) -> std::io::Result<Program> {
+    let program = load_program(path, evm)?;
+    if let Program::Code(code) = &program {
+        validate_bytecode(code)
+            .map_err(|err| invalid_data(format!("{}: {err}", path.display())))?;
+    }
+    Ok(program)
+}
+
+pub fn hex(code: &[u8]) -> String {
+    code.iter()
+        .map(|b| format!("{:02x}", b))
+        .collect::<Vec<_>>()
+        .join("")
+}
+
+fn parse_hex(text: &str) -> Option<Vec<u8>> {
+    let digits = text.strip_prefix("0x").unwrap_or(text);
+    (0..digits.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(digits.get(i..i + 2)?, 16).ok())
+        .collect()
+}
+
+/// Reads a hex file, ignoring whitespace and `#` comments.
+pub fn read_hex(path: &Path) -> std::io::Result<Vec<u8>> {
+    let text = std::fs::read_to_string(path).map_err(|err| in_file(path, err))?;
+    let digits: String = text
+        .lines()
+        .map(|line| line.split('#').next().unwrap())
+        .flat_map(|line| line.chars().filter(|c| !c.is_whitespace()))
+        .collect();
+    parse_hex(&digits).ok_or_else(|| invalid_data(format!("{}: invalid hex", path.display())))
+}
+
+/// `err`, naming the file it happened on.
+fn in_file(path: &Path, err: std::io::Error) -> std::io::Error {
+    std::io::Error::new(err.kind(), format!("{}: {err}", path.display()))
+}
+
+fn invalid_data(message: String) -> std::io::Error {
+    std::io::Error::new(std::io::ErrorKind::InvalidData, message)
+}
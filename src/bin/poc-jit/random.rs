@@ -0,0 +1,495 @@
+//! Random programs for the benchmark and the fuzzer: containers whose code
+//! keeps the stack shallow, enters subroutines and CALLs [`CALLEE`], with
+//! random data, call data and environment.
+
+use poc_jit::container::MAX_STACK_HEIGHT;
+use poc_jit::instructions::{self, opcodes::*};
+use poc_jit::{Container, Environment};
+use rand::Rng;
+use std::collections::BTreeMap;
+use std::ops::Range;
+
+pub const CALLDATA_LEN: usize = 64;
+pub const DATA_LEN: usize = 64;
+
+/// Program deployed as `CALLEE_ID` for generated code to CALL: stores the sum
+/// of its first two input words in slot 0 and returns it.
+pub const CALLEE: &[u8] = &[
+    PUSH,
+    0,
+    CALLDATALOAD,
+    PUSH,
+    8,
+    CALLDATALOAD,
+    ADD,
+    DUP1,
+    SSTORE,
+    0,
+    PUSH,
+    0,
+    MSTORE,
+    PUSH,
+    8,
+    PUSH,
+    0,
+    RETURN,
+];
+pub const CALLEE_ID: u8 = 0;
+
+/// A generated program and the inputs it runs with.
+pub struct Case {
+    /// The code image of `container`.
+    pub code: Vec<u8>,
+    pub container: Container,
+    pub calldata: Vec<u8>,
+    pub env: Environment,
+}
+
+/// Generates a program of `len` instructions, picked with `weights`, in a
+/// container with random data, and random inputs for it.
+pub fn random_case(rng: &mut impl Rng, len: usize, weights: &Weights) -> Case {
+    let (code, sections) = random_program(rng, len, weights);
+    let mut data = vec![0u8; DATA_LEN];
+    rng.fill(&mut data[..]);
+    let sections: Vec<&[u8]> = sections.into_iter().map(|range| &code[range]).collect();
+    let container = Container::parse(&Container::encode(&sections, &data, MAX_STACK_HEIGHT))
+        .expect("generated program failed validation");
+    let mut calldata = vec![0u8; CALLDATA_LEN];
+    rng.fill(&mut calldata[..]);
+    let env = Environment {
+        caller: rng.gen_range(0..1000),
+        address: rng.gen_range(1000..2000),
+        callvalue: rng.gen_range(0..=u64::MAX),
+        number: rng.gen_range(0..20_000_000),
+        timestamp: rng.gen_range(1_600_000_000..1_800_000_000),
+        chain_id: 1,
+    };
+    Case {
+        code,
+        container,
+        calldata,
+        env,
+    }
+}
+
+/// Relative weights of the instructions the generator picks from, by
+/// opcode. `DUP1`, `SWAP1` and `LOG0` stand for all their variants.
+#[derive(Clone)]
+pub struct Weights([u32; 256]);
+
+impl Default for Weights {
+    /// Every instruction equally likely.
+    fn default() -> Self {
+        Weights([1; 256])
+    }
+}
+
+impl Weights {
+    /// The weights in `named`, by [`weight_name`], and 1 for the rest. Fails
+    /// on a name the generator never emits, or if the weights leave no
+    /// instruction to pick at some stack depth.
+    pub fn new(named: &BTreeMap<String, u32>) -> Result<Self, String> {
+        let mut weights = Weights::default();
+        for (name, &weight) in named {
+            let Some(&op) = ANY_DEPTH.iter().find(|&&op| weight_name(op) == *name) else {
+                let names: Vec<String> = ANY_DEPTH.iter().map(|&op| weight_name(op)).collect();
+                return Err(format!(
+                    "no instruction {name} to weight, expected one of {}",
+                    names.join(", ")
+                ));
+            };
+            weights.0[op as usize] = weight;
+        }
+        for depth in 0..=MAX_DEPTH {
+            if choices(depth).iter().all(|&op| weights.0[op as usize] == 0) {
+                return Err(format!(
+                    "the weights leave no instruction to pick at stack depth {depth}"
+                ));
+            }
+        }
+        Ok(weights)
+    }
+
+    fn pick(&self, rng: &mut impl Rng, choices: &[u8]) -> u8 {
+        let total: u64 = choices.iter().map(|&op| self.0[op as usize] as u64).sum();
+        let mut n = rng.gen_range(0..total);
+        for &op in choices {
+            let weight = self.0[op as usize] as u64;
+            if n < weight {
+                return op;
+            }
+            n -= weight;
+        }
+        unreachable!()
+    }
+}
+
+/// Name of the weight of `op`: its mnemonic, or `DUPn`, `SWAPn` and `LOGn`
+/// for the families.
+pub fn weight_name(op: u8) -> String {
+    match op {
+        DUP1 => "DUPn".to_string(),
+        SWAP1 => "SWAPn".to_string(),
+        LOG0 => "LOGn".to_string(),
+        _ => instructions::mnemonic(op).unwrap(),
+    }
+}
+
+/// Depth the generator keeps the stack under.
+const MAX_DEPTH: usize = 8;
+
+/// Instructions picked from below depth 2.
+const SHALLOW: &[u8] = &[
+    PUSH,
+    SLOAD,
+    DUP,
+    CALLDATASIZE,
+    CALL,
+    KECCAK256,
+    CALLER,
+    NUMBER,
+    GAS,
+    PC,
+    TLOAD,
+    DATALOADN,
+    DATASIZE,
+    CALLSUB,
+];
+/// Instructions picked from at depth 2.
+const DEPTH_2: &[u8] = &[
+    PUSH,
+    SLOAD,
+    SSTORE,
+    ADD,
+    SUB,
+    MUL,
+    DIV,
+    MOD,
+    EQ,
+    LT,
+    GT,
+    AND,
+    OR,
+    XOR,
+    DUP,
+    SWAP,
+    CALLDATALOAD,
+    CALLDATASIZE,
+    MLOAD,
+    MSTORE,
+    SDIV,
+    SMOD,
+    SLT,
+    SGT,
+    SIGNEXTEND,
+    SHL,
+    SHR,
+    SAR,
+    NOT,
+    ISZERO,
+    BYTE,
+    EXP,
+    POP,
+    DUP1,
+    SWAP1,
+    CALL,
+    LOG0,
+    KECCAK256,
+    CALLER,
+    ADDRESS,
+    CALLVALUE,
+    NUMBER,
+    TIMESTAMP,
+    CHAINID,
+    GAS,
+    PC,
+    TLOAD,
+    TSTORE,
+    DATALOAD,
+    DATALOADN,
+    CALLSUB,
+];
+/// Instructions picked from at depths 3 up to `MAX_DEPTH`; every
+/// instruction the generator emits.
+const ANY_DEPTH: &[u8] = &[
+    PUSH,
+    SLOAD,
+    SSTORE,
+    ADD,
+    SUB,
+    MUL,
+    DIV,
+    MOD,
+    EQ,
+    LT,
+    GT,
+    AND,
+    OR,
+    XOR,
+    DUP,
+    SWAP,
+    CALLDATALOAD,
+    CALLDATASIZE,
+    CALLDATACOPY,
+    MLOAD,
+    MSTORE,
+    SDIV,
+    SMOD,
+    SLT,
+    SGT,
+    SIGNEXTEND,
+    SHL,
+    SHR,
+    SAR,
+    NOT,
+    ISZERO,
+    BYTE,
+    ADDMOD,
+    MULMOD,
+    EXP,
+    POP,
+    DUP1,
+    SWAP1,
+    CALL,
+    LOG0,
+    KECCAK256,
+    CALLER,
+    ADDRESS,
+    CALLVALUE,
+    NUMBER,
+    TIMESTAMP,
+    CHAINID,
+    GAS,
+    PC,
+    TLOAD,
+    TSTORE,
+    DATALOAD,
+    DATALOADN,
+    DATASIZE,
+    DATACOPY,
+    CALLSUB,
+];
+/// Instructions picked from at `MAX_DEPTH` and over, which shrink the stack.
+const FULL: &[u8] = &[
+    SSTORE,
+    ADD,
+    SUB,
+    MUL,
+    DIV,
+    MOD,
+    EQ,
+    LT,
+    GT,
+    AND,
+    OR,
+    XOR,
+    SWAP,
+    MSTORE,
+    CALLDATACOPY,
+    SDIV,
+    SMOD,
+    SLT,
+    SGT,
+    SIGNEXTEND,
+    SHL,
+    SHR,
+    SAR,
+    BYTE,
+    ADDMOD,
+    MULMOD,
+    EXP,
+    POP,
+    SWAP1,
+    TSTORE,
+    DATACOPY,
+];
+
+fn choices(depth: usize) -> &'static [u8] {
+    if depth < 2 {
+        SHALLOW
+    } else if depth >= MAX_DEPTH {
+        FULL
+    } else if depth < 3 {
+        DEPTH_2
+    } else {
+        ANY_DEPTH
+    }
+}
+
+/// Generates a code image and its sections: a main section of `len`
+/// instructions ending in STOP, then one or two subroutines for its CALLSUBs.
+/// A subroutine calls the ones after it, and in an eighth of the programs
+/// itself too: without branches, recursion always runs out of return stack,
+/// stack or gas.
+fn random_program(
+    rng: &mut impl Rng,
+    len: usize,
+    weights: &Weights,
+) -> (Vec<u8>, Vec<Range<usize>>) {
+    let subroutines = rng.gen_range(1..=2);
+    let recursive = rng.gen_ratio(1, 8);
+    let mut code = Vec::new();
+    let mut calls = Vec::new();
+    random_body(rng, len, weights, 0..subroutines, &mut code, &mut calls);
+    code.push(STOP);
+
+    let mut sections = Vec::new();
+    sections.push(0..code.len());
+    for sub in 0..subroutines {
+        let start = code.len();
+        let len = rng.gen_range(1..=len / 4 + 1);
+        code.push(BEGINSUB);
+        let callees = if recursive { sub } else { sub + 1 }..subroutines;
+        // return with the stack the subroutine was entered with
+        let depth = random_body(rng, len, weights, callees, &mut code, &mut calls);
+        code.extend(std::iter::repeat_n(POP, depth));
+        code.push(RETSUB);
+        sections.push(start..code.len());
+    }
+
+    for (pc, sub) in calls {
+        let target = sections[sub + 1].start as u16;
+        code[pc + 1..pc + 3].copy_from_slice(&target.to_be_bytes());
+    }
+    (code, sections)
+}
+
+/// Appends `len` instructions to `code` from an empty stack and returns the
+/// depth they leave. CALLSUBs enter one of `subroutines`, recorded in `calls`
+/// by pc for their targets to be filled in; with none to enter, a picked
+/// CALLSUB is left out.
+fn random_body(
+    rng: &mut impl Rng,
+    len: usize,
+    weights: &Weights,
+    subroutines: Range<usize>,
+    code: &mut Vec<u8>,
+    calls: &mut Vec<(usize, usize)>,
+) -> usize {
+    let mut depth = 0;
+
+    for _ in 0..len {
+        let op = weights.pick(rng, choices(depth));
+        match op {
+            PUSH => {
+                let val = rng.gen_range(1..=255) as u8;
+                code.push(PUSH);
+                code.push(val);
+                depth += 1;
+            }
+            SLOAD | TLOAD => {
+                let key = rng.gen_range(0..32) as u8;
+                code.push(op);
+                code.push(key);
+                depth += 1;
+            }
+            SSTORE | TSTORE => {
+                let key = rng.gen_range(0..32) as u8;
+                code.push(op);
+                code.push(key);
+                depth = depth.saturating_sub(1);
+            }
+            ADD | SUB | MUL | DIV | MOD | EQ | LT | GT | AND | OR | XOR | SDIV | SMOD | SLT
+            | SGT | SIGNEXTEND | SHL | SHR | SAR | BYTE | EXP => {
+                code.push(op);
+                if depth >= 2 {
+                    depth -= 1;
+                }
+            }
+            DUP => {
+                code.push(DUP);
+                if depth > 0 {
+                    depth += 1;
+                }
+            }
+            SWAP | CALLDATALOAD | MLOAD | NOT | ISZERO | DATALOAD => {
+                code.push(op);
+            }
+            CALLDATASIZE | CALLER | ADDRESS | CALLVALUE | NUMBER | TIMESTAMP | CHAINID | GAS
+            | PC | DATASIZE => {
+                code.push(op);
+                depth += 1;
+            }
+            DATALOADN => {
+                let offset = rng.gen_range(0..=DATA_LEN - 8) as u16;
+                code.push(DATALOADN);
+                code.extend(offset.to_be_bytes());
+                depth += 1;
+            }
+            MSTORE => {
+                code.push(MSTORE);
+                depth -= 2;
+            }
+            CALLDATACOPY | DATACOPY => {
+                code.push(op);
+                depth -= 3;
+            }
+            POP => {
+                code.push(POP);
+                depth -= 1;
+            }
+            DUP1 => {
+                let n = rng.gen_range(1..=depth.min(16));
+                code.push(DUP1 + (n - 1) as u8);
+                depth += 1;
+            }
+            SWAP1 => {
+                let n = rng.gen_range(1..=(depth - 1).min(16));
+                code.push(SWAP1 + (n - 1) as u8);
+            }
+            ADDMOD | MULMOD => {
+                code.push(op);
+                depth -= 2;
+            }
+            CALL => {
+                // pass 16 bytes of memory to the callee and take back its
+                // 8-byte result; small gas budgets make some calls fail
+                code.extend([
+                    PUSH,
+                    8,
+                    PUSH,
+                    rng.gen_range(0..=255),
+                    PUSH,
+                    16,
+                    PUSH,
+                    rng.gen_range(0..=255),
+                    PUSH,
+                    CALLEE_ID,
+                    PUSH,
+                    rng.gen_range(1..=255),
+                    CALL,
+                ]);
+                depth += 1;
+            }
+            LOG0 => {
+                // topics come from the stack, the data range is pushed
+                let topics = rng.gen_range(0..=depth.min(4));
+                code.extend([
+                    PUSH,
+                    rng.gen_range(0..=32),
+                    PUSH,
+                    rng.gen_range(0..=255),
+                    LOG0 + topics as u8,
+                ]);
+                depth -= topics;
+            }
+            KECCAK256 => {
+                code.extend([
+                    PUSH,
+                    rng.gen_range(0..=64),
+                    PUSH,
+                    rng.gen_range(0..=255),
+                    KECCAK256,
+                ]);
+                depth += 1;
+            }
+            CALLSUB if subroutines.is_empty() => {}
+            CALLSUB => {
+                calls.push((code.len(), rng.gen_range(subroutines.clone())));
+                code.extend([CALLSUB, 0, 0]);
+            }
+            _ => unreachable!(),
+        }
+    }
+    depth
+}
@@ -0,0 +1,43 @@
+//! Execution settings shared by the interpreter and the JIT, so both engines
+//! enforce the same limits.
+
+/// Default maximum operand stack depth, as in the EVM.
+pub const DEFAULT_STACK_LIMIT: usize = 1024;
+
+/// Largest stack limit the C API and the command line accept. The host
+/// allocates a stack of this many words for each nested JIT frame, so
+/// 512 KiB per frame.
+pub const MAX_STACK_LIMIT: usize = 1 << 16;
+
+/// What an instruction does when the operand stack holds fewer items than
+/// it needs.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum UnderflowPolicy {
+    /// Missing operands read as zero and the stack is left empty below the
+    /// results. DUPn of a missing item pushes zero, while SWAPn with a
+    /// missing item and POP on an empty stack do nothing.
+    #[default]
+    ZeroFill,
+    /// Halt the frame with `StackUnderflow`, consuming all gas. DUPn needs n
+    /// items and SWAPn n + 1.
+    Trap,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Config {
+    /// Maximum number of items on the operand stack of each call frame. An
+    /// instruction that would grow the stack past it halts the frame with
+    /// `StackOverflow`. The host allocates an operand stack of this many
+    /// words for every JIT frame.
+    pub stack_limit: usize,
+    pub underflow: UnderflowPolicy,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            stack_limit: DEFAULT_STACK_LIMIT,
+            underflow: UnderflowPolicy::default(),
+        }
+    }
+}
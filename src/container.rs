@@ -0,0 +1,339 @@
+//! Versioned bytecode container, loosely modelled on the EVM Object Format.
+//!
+//! ```text
+//! magic       0xEF 0x00
+//! version     0x01
+//! code        0x01, section count (u16), size of each section (u16 each)
+//! data        0x02, size (u16)
+//! stack       0x03, max stack height (u16)
+//! terminator  0x00
+//! body        code sections back to back, then the data section
+//! ```
+//!
+//! All integers are big-endian. The code sections form a single code image:
+//! execution starts at the first one, every later one is a subroutine that
+//! starts with BEGINSUB, and CALLSUB targets are offsets into the image. A
+//! container is validated once by [`Container::parse`]; the engines then
+//! reuse what validation computed instead of scanning the code again. The
+//! declared max stack height is not checked against the code, only enforced
+//! while it runs.
+
+use crate::instructions::{self, opcodes::*};
+use std::fmt;
+use std::ops::Range;
+
+pub const MAGIC: [u8; 2] = [0xEF, 0x00];
+pub const VERSION: u8 = 1;
+
+/// Largest max stack height a container may declare.
+pub const MAX_STACK_HEIGHT: u16 = 1024;
+
+const KIND_CODE: u8 = 0x01;
+const KIND_DATA: u8 = 0x02;
+const KIND_STACK: u8 = 0x03;
+const TERMINATOR: u8 = 0x00;
+
+/// Why a container was rejected. Code offsets are relative to the start of
+/// the code image.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum ContainerError {
+    InvalidMagic,
+    UnsupportedVersion(u8),
+    /// The header ended early or a section kind is not where it should be.
+    InvalidHeader,
+    NoCodeSection,
+    EmptyCodeSection(usize),
+    /// The body is not as long as the header declares.
+    SizeMismatch {
+        expected: usize,
+        actual: usize,
+    },
+    UndefinedOpcode {
+        pc: usize,
+        op: u8,
+    },
+    /// An instruction's immediate runs past the end of its section.
+    TruncatedImmediate {
+        pc: usize,
+    },
+    /// A section does not end with STOP, RETURN or RETSUB.
+    MissingTerminator(usize),
+    /// A section other than the first does not start with BEGINSUB.
+    NotASubroutine(usize),
+    /// A CALLSUB whose target is not a BEGINSUB.
+    InvalidCallTarget {
+        pc: usize,
+        target: usize,
+    },
+    /// A DATALOADN whose word does not lie entirely in the data section.
+    InvalidDataOffset {
+        pc: usize,
+        offset: usize,
+    },
+    StackHeightTooLarge(u16),
+}
+
+impl fmt::Display for ContainerError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match *self {
+            ContainerError::InvalidMagic => write!(f, "invalid magic"),
+            ContainerError::UnsupportedVersion(version) => {
+                write!(f, "unsupported version {version}")
+            }
+            ContainerError::InvalidHeader => write!(f, "malformed header"),
+            ContainerError::NoCodeSection => write!(f, "no code section"),
+            ContainerError::EmptyCodeSection(i) => write!(f, "code section {i} is empty"),
+            ContainerError::SizeMismatch { expected, actual } => {
+                write!(f, "body is {actual} bytes, header declares {expected}")
+            }
+            ContainerError::UndefinedOpcode { pc, op } => {
+                write!(f, "undefined opcode 0x{op:02x} at pc {pc}")
+            }
+            ContainerError::TruncatedImmediate { pc } => {
+                write!(f, "truncated immediate at pc {pc}")
+            }
+            ContainerError::MissingTerminator(i) => {
+                write!(
+                    f,
+                    "code section {i} does not end with STOP, RETURN or RETSUB"
+                )
+            }
+            ContainerError::NotASubroutine(i) => {
+                write!(f, "code section {i} does not start with BEGINSUB")
+            }
+            ContainerError::InvalidCallTarget { pc, target } => {
+                write!(
+                    f,
+                    "CALLSUB at pc {pc} targets {target}, which is not a BEGINSUB"
+                )
+            }
+            ContainerError::InvalidDataOffset { pc, offset } => {
+                write!(
+                    f,
+                    "DATALOADN at pc {pc} reads past the data section at {offset}"
+                )
+            }
+            ContainerError::StackHeightTooLarge(height) => {
+                write!(f, "max stack height {height} exceeds {MAX_STACK_HEIGHT}")
+            }
+        }
+    }
+}
+
+impl std::error::Error for ContainerError {}
+
+/// A validated container.
+#[derive(Clone, Debug)]
+pub struct Container {
+    code: Vec<u8>,
+    sections: Vec<Range<usize>>,
+    data: Vec<u8>,
+    max_stack_height: u16,
+    entries: Vec<bool>,
+}
+
+impl Container {
+    /// Parses and validates a container.
+    pub fn parse(bytes: &[u8]) -> Result<Container, ContainerError> {
+        let mut reader = Reader { bytes, pos: 0 };
+        if reader.take(2) != Some(&MAGIC[..]) {
+            return Err(ContainerError::InvalidMagic);
+        }
+        match reader.u8() {
+            Some(VERSION) => {}
+            Some(version) => return Err(ContainerError::UnsupportedVersion(version)),
+            None => return Err(ContainerError::InvalidHeader),
+        }
+
+        reader.expect(KIND_CODE)?;
+        let count = reader.u16()? as usize;
+        if count == 0 {
+            return Err(ContainerError::NoCodeSection);
+        }
+        let mut sections = Vec::with_capacity(count);
+        let mut start = 0;
+        for i in 0..count {
+            let size = reader.u16()? as usize;
+            if size == 0 {
+                return Err(ContainerError::EmptyCodeSection(i));
+            }
+            sections.push(start..start + size);
+            start += size;
+        }
+        reader.expect(KIND_DATA)?;
+        let data_size = reader.u16()? as usize;
+        reader.expect(KIND_STACK)?;
+        let max_stack_height = reader.u16()?;
+        reader.expect(TERMINATOR)?;
+
+        let body = &bytes[reader.pos..];
+        let expected = start + data_size;
+        if body.len() != expected {
+            return Err(ContainerError::SizeMismatch {
+                expected,
+                actual: body.len(),
+            });
+        }
+        if max_stack_height > MAX_STACK_HEIGHT {
+            return Err(ContainerError::StackHeightTooLarge(max_stack_height));
+        }
+
+        let code = body[..start].to_vec();
+        let data = body[start..].to_vec();
+        let entries = validate_code(&code, &sections, data.len())?;
+        Ok(Container {
+            code,
+            sections,
+            data,
+            max_stack_height,
+            entries,
+        })
+    }
+
+    /// Encodes a container from its parts without validating them. Panics
+    /// if a section does not fit the header's u16 fields.
+    pub fn encode(sections: &[&[u8]], data: &[u8], max_stack_height: u16) -> Vec<u8> {
+        let size = |len: usize| u16::try_from(len).expect("section too large").to_be_bytes();
+        let count = u16::try_from(sections.len()).expect("too many sections");
+        let mut bytes = MAGIC.to_vec();
+        bytes.push(VERSION);
+        bytes.push(KIND_CODE);
+        bytes.extend(count.to_be_bytes());
+        for section in sections {
+            bytes.extend(size(section.len()));
+        }
+        bytes.push(KIND_DATA);
+        bytes.extend(size(data.len()));
+        bytes.push(KIND_STACK);
+        bytes.extend(max_stack_height.to_be_bytes());
+        bytes.push(TERMINATOR);
+        for section in sections {
+            bytes.extend_from_slice(section);
+        }
+        bytes.extend_from_slice(data);
+        bytes
+    }
+
+    /// The code image: all code sections back to back.
+    pub fn code(&self) -> &[u8] {
+        &self.code
+    }
+
+    /// Range of the code image covered by each code section.
+    pub fn sections(&self) -> &[Range<usize>] {
+        &self.sections
+    }
+
+    pub fn data(&self) -> &[u8] {
+        &self.data
+    }
+
+    /// Most items the operand stack may hold; both engines halt with
+    /// `StackOverflow` past it. This is a runtime limit only: parsing checks
+    /// it is at most [`MAX_STACK_HEIGHT`], not that the code stays under it.
+    pub fn max_stack_height(&self) -> u16 {
+        self.max_stack_height
+    }
+
+    /// The valid CALLSUB targets, as computed by
+    /// [`instructions::subroutine_entries`].
+    pub fn subroutine_entries(&self) -> &[bool] {
+        &self.entries
+    }
+}
+
+/// Checks every instruction of every section and returns the subroutine
+/// entries of the code image.
+fn validate_code(
+    code: &[u8],
+    sections: &[Range<usize>],
+    data_len: usize,
+) -> Result<Vec<bool>, ContainerError> {
+    for (i, section) in sections.iter().enumerate() {
+        if i > 0 && code[section.start] != BEGINSUB {
+            return Err(ContainerError::NotASubroutine(i));
+        }
+        let last = check_instructions(code, section.clone())?;
+        if !matches!(code[last], STOP | RETURN | RETSUB) {
+            return Err(ContainerError::MissingTerminator(i));
+        }
+    }
+
+    // sections end on instruction boundaries, so the image scans as a whole
+    let entries = instructions::subroutine_entries(code);
+    let mut pc = 0;
+    while pc < code.len() {
+        match code[pc] {
+            CALLSUB => {
+                let target = u16::from_be_bytes([code[pc + 1], code[pc + 2]]) as usize;
+                if !entries.get(target).copied().unwrap_or(false) {
+                    return Err(ContainerError::InvalidCallTarget { pc, target });
+                }
+            }
+            DATALOADN => {
+                let offset = u16::from_be_bytes([code[pc + 1], code[pc + 2]]) as usize;
+                if offset + 8 > data_len {
+                    return Err(ContainerError::InvalidDataOffset { pc, offset });
+                }
+            }
+            _ => {}
+        }
+        pc += 1 + instructions::immediate_len(code[pc]);
+    }
+    Ok(entries)
+}
+
+/// Checks raw bytecode the way [`Container::parse`] checks the instructions
+/// of a code section: every opcode is defined and every immediate is
+/// complete. The engines only run code that passes.
+pub fn validate_bytecode(code: &[u8]) -> Result<(), ContainerError> {
+    check_instructions(code, 0..code.len()).map(|_| ())
+}
+
+/// Checks the instructions in `range` of `code` and returns the pc of the
+/// last one.
+fn check_instructions(code: &[u8], range: Range<usize>) -> Result<usize, ContainerError> {
+    let mut pc = range.start;
+    let mut last = pc;
+    while pc < range.end {
+        let op = code[pc];
+        if !instructions::is_defined(op) {
+            return Err(ContainerError::UndefinedOpcode { pc, op });
+        }
+        last = pc;
+        pc += 1 + instructions::immediate_len(op);
+    }
+    if pc > range.end {
+        return Err(ContainerError::TruncatedImmediate { pc: last });
+    }
+    Ok(last)
+}
+
+struct Reader<'a> {
+    bytes: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn take(&mut self, n: usize) -> Option<&'a [u8]> {
+        let bytes = self.bytes.get(self.pos..self.pos + n)?;
+        self.pos += n;
+        Some(bytes)
+    }
+
+    fn u8(&mut self) -> Option<u8> {
+        self.take(1).map(|b| b[0])
+    }
+
+    fn u16(&mut self) -> Result<u16, ContainerError> {
+        let bytes = self.take(2).ok_or(ContainerError::InvalidHeader)?;
+        Ok(u16::from_be_bytes([bytes[0], bytes[1]]))
+    }
+
+    fn expect(&mut self, kind: u8) -> Result<(), ContainerError> {
+        match self.u8() {
+            Some(b) if b == kind => Ok(()),
+            _ => Err(ContainerError::InvalidHeader),
+        }
+    }
+}
@@ -0,0 +1,27 @@
+/// Transaction and block context visible to a program. Compiled code reads
+/// the fields directly, hence the fixed layout.
+#[repr(C)]
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Environment {
+    /// Id of the program (or account) that started this call.
+    pub caller: u64,
+    /// Id of the running program. CALL sets it to the callee's program id.
+    pub address: u64,
+    /// Value sent along with the call; always 0 for nested CALLs.
+    pub callvalue: u64,
+    pub number: u64,
+    pub timestamp: u64,
+    pub chain_id: u64,
+}
+
+impl Environment {
+    /// Context of a CALL from the program running in `self` to `callee`.
+    pub fn for_callee(&self, callee: u64) -> Environment {
+        Environment {
+            caller: self.address,
+            address: callee,
+            callvalue: 0,
+            ..*self
+        }
+    }
+}
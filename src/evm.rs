@@ -0,0 +1,221 @@
+//! Front end for bytecode using the standard EVM opcode numbering.
+//!
+//! [`decode`] translates the subset of EVM opcodes the engines implement into
+//! this crate's instruction set, so real contract snippets can be run by both
+//! `VM::interpret` and `make_jit`. Words are 64 bits wide: PUSH values and
+//! results are truncated to the low 8 bytes, and gas follows this crate's
+//! schedule rather than the EVM's.
+//!
+//! Memory and call data keep the EVM's byte layout, so a 32-byte EVM word at
+//! offset `o` is modelled by its low 8 bytes at `o + 24`: CALLDATALOAD and
+//! MLOAD read there, and MSTORE writes there after zeroing the 24 bytes
+//! above. BYTE indexes the 32-byte word, so index `i` is byte `i - 24` of
+//! ours, and the bytes above it read as zero. CALLDATACOPY copies bytes in
+//! both and needs no translation.
+//!
+//! A few opcodes take their operands from the stack in the EVM but from an
+//! immediate here: SLOAD, SSTORE, TLOAD and TSTORE are only accepted right
+//! after a PUSH of a key below 256, which is folded into the instruction.
+
+use crate::instructions::opcodes::*;
+use std::fmt;
+
+/// Why a piece of EVM bytecode cannot be translated.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum DecodeError {
+    /// The opcode at `pc` has no equivalent in the engines.
+    UnsupportedOpcode { pc: usize, op: u8 },
+    /// A storage opcode at `pc` whose key is not a PUSHed constant below 256.
+    DynamicStorageKey { pc: usize, op: u8 },
+}
+
+impl fmt::Display for DecodeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match *self {
+            DecodeError::UnsupportedOpcode { pc, op } => {
+                write!(f, "unsupported EVM opcode {} at pc {pc}", describe(op))
+            }
+            DecodeError::DynamicStorageKey { pc, op } => write!(
+                f,
+                "{} at pc {pc} needs its key PUSHed right before it, below 256",
+                describe(op)
+            ),
+        }
+    }
+}
+
+impl std::error::Error for DecodeError {}
+
+/// Translates EVM bytecode into this crate's instruction set. PC pushes the
+/// offset in the original bytecode, and PUSH data running past the end reads
+/// as zero, as in the EVM.
+pub fn decode(bytecode: &[u8]) -> Result<Vec<u8>, DecodeError> {
+    let mut code = Vec::with_capacity(bytecode.len());
+    let mut pc = 0;
+    while pc < bytecode.len() {
+        let op = bytecode[pc];
+        match op {
+            0x00 => code.push(STOP),
+            0x01 => code.push(ADD),
+            0x02 => code.push(MUL),
+            // the engines take the left-hand operand from below the top
+            0x03 => code.extend([SWAP1, SUB]),
+            0x04 => code.extend([SWAP1, DIV]),
+            0x05 => code.extend([SWAP1, SDIV]),
+            0x06 => code.extend([SWAP1, MOD]),
+            0x07 => code.extend([SWAP1, SMOD]),
+            0x08 => code.push(ADDMOD),
+            0x09 => code.push(MULMOD),
+            0x0a => code.push(EXP),
+            0x0b => code.push(SIGNEXTEND),
+            0x10 => code.extend([SWAP1, LT]),
+            0x11 => code.extend([SWAP1, GT]),
+            0x12 => code.extend([SWAP1, SLT]),
+            0x13 => code.extend([SWAP1, SGT]),
+            0x14 => code.push(EQ),
+            0x15 => code.push(ISZERO),
+            0x16 => code.push(AND),
+            0x17 => code.push(OR),
+            0x18 => code.push(XOR),
+            0x19 => code.push(NOT),
+            // out of range, and so zero, below 24 once wrapped
+            0x1a => code.extend([PUSH, 24, SUB, BYTE]),
+            0x1b => code.push(SHL),
+            0x1c => code.push(SHR),
+            0x1d => code.push(SAR),
+            0x20 => code.push(KECCAK256),
+            0x30 => code.push(ADDRESS),
+            0x33 => code.push(CALLER),
+            0x34 => code.push(CALLVALUE),
+            0x35 => code.extend([PUSH, 24, ADD, CALLDATALOAD]),
+            0x36 => code.push(CALLDATASIZE),
+            0x37 => code.push(CALLDATACOPY),
+            0x42 => code.push(TIMESTAMP),
+            0x43 => code.push(NUMBER),
+            0x46 => code.push(CHAINID),
+            0x50 => code.push(POP),
+            0x51 => code.extend([PUSH, 24, ADD, MLOAD]),
+            0x52 => store_word(&mut code),
+            0x58 => push_const(&mut code, pc as u64),
+            0x5a => code.push(GAS),
+            0x54 | 0x55 | 0x5c | 0x5d => {
+                return Err(DecodeError::DynamicStorageKey { pc, op });
+            }
+            0x5f..=0x7f => {
+                let len = (op - 0x5f) as usize;
+                let value = push_value(bytecode, pc + 1, len);
+                pc += 1 + len;
+                let keyed = match bytecode.get(pc) {
+                    Some(0x54) => Some(SLOAD),
+                    Some(0x55) => Some(SSTORE),
+                    Some(0x5c) => Some(TLOAD),
+                    Some(0x5d) => Some(TSTORE),
+                    _ => None,
+                };
+                match keyed {
+                    Some(keyed) if value < 256 => {
+                        code.extend([keyed, value as u8]);
+                        pc += 1;
+                    }
+                    _ => push_const(&mut code, value),
+                }
+                continue;
+            }
+            0x80..=0x8f => code.push(DUP1 + (op - 0x80)),
+            0x90..=0x9f => code.push(SWAP1 + (op - 0x90)),
+            0xa0..=0xa4 => code.push(LOG0 + (op - 0xa0)),
+            0xf3 => code.push(RETURN),
+            _ => return Err(DecodeError::UnsupportedOpcode { pc, op }),
+        }
+        pc += 1;
+    }
+    Ok(code)
+}
+
+/// Reads `len` bytes of PUSH data at `start`, zero-padded past the end of
+/// `bytecode`, keeping the low 8 bytes.
+fn push_value(bytecode: &[u8], start: usize, len: usize) -> u64 {
+    (start..start + len).fold(0u64, |value, i| {
+        (value << 8) | *bytecode.get(i).unwrap_or(&0) as u64
+    })
+}
+
+/// Emits code pushing `value`, built a byte at a time since PUSH only takes
+/// a 1-byte immediate.
+fn push_const(code: &mut Vec<u8>, value: u64) {
+    let bytes = value.to_be_bytes();
+    let first = bytes.iter().position(|&b| b != 0).unwrap_or(7);
+    code.extend([PUSH, bytes[first]]);
+    for &byte in &bytes[first + 1..] {
+        code.extend([PUSH, 8, SHL]);
+        if byte != 0 {
+            code.extend([PUSH, byte, OR]);
+        }
+    }
+}
+
+/// Emits an EVM MSTORE of the value below the offset on top of the stack:
+/// zeroes the high 24 bytes of the word a word at a time, then stores the
+/// value in its low 8 bytes.
+fn store_word(code: &mut Vec<u8>) {
+    for high in [0, 8, 16] {
+        code.push(DUP1);
+        if high != 0 {
+            code.extend([PUSH, high, ADD]);
+        }
+        code.extend([PUSH, 0, SWAP1, MSTORE]);
+    }
+    code.extend([PUSH, 24, ADD, MSTORE]);
+}
+
+/// Mnemonic and value of an EVM opcode for error messages.
+fn describe(op: u8) -> String {
+    match mnemonic(op) {
+        Some(name) => format!("{name} (0x{op:02x})"),
+        None => format!("0x{op:02x}"),
+    }
+}
+
+/// Names of the EVM opcodes `decode` can reject.
+fn mnemonic(op: u8) -> Option<&'static str> {
+    Some(match op {
+        0x54 => "SLOAD",
+        0x55 => "SSTORE",
+        0x5c => "TLOAD",
+        0x5d => "TSTORE",
+        0x31 => "BALANCE",
+        0x32 => "ORIGIN",
+        0x38 => "CODESIZE",
+        0x39 => "CODECOPY",
+        0x3a => "GASPRICE",
+        0x3b => "EXTCODESIZE",
+        0x3c => "EXTCODECOPY",
+        0x3d => "RETURNDATASIZE",
+        0x3e => "RETURNDATACOPY",
+        0x3f => "EXTCODEHASH",
+        0x40 => "BLOCKHASH",
+        0x41 => "COINBASE",
+        0x44 => "PREVRANDAO",
+        0x45 => "GASLIMIT",
+        0x47 => "SELFBALANCE",
+        0x48 => "BASEFEE",
+        0x49 => "BLOBHASH",
+        0x4a => "BLOBBASEFEE",
+        0x53 => "MSTORE8",
+        0x56 => "JUMP",
+        0x57 => "JUMPI",
+        0x59 => "MSIZE",
+        0x5b => "JUMPDEST",
+        0x5e => "MCOPY",
+        0xf0 => "CREATE",
+        0xf1 => "CALL",
+        0xf2 => "CALLCODE",
+        0xf4 => "DELEGATECALL",
+        0xf5 => "CREATE2",
+        0xfa => "STATICCALL",
+        0xfd => "REVERT",
+        0xfe => "INVALID",
+        0xff => "SELFDESTRUCT",
+        _ => return None,
+    })
+}
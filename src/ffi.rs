@@ -0,0 +1,329 @@
+//! C API for embedding both engines, built with the `ffi` feature. Its
+//! header, `include/poc_jit.h`, is generated from this module by the build
+//! script.
+//!
+//! An engine holds a config and the state programs run against. A program is
+//! bytecode or a container, run by the interpreter until an engine compiles
+//! it. Storage belongs to the embedder: executing a program reads the keys
+//! its SLOAD and SSTORE instructions use through the caller's callbacks, and
+//! writes back the ones whose value changed once the run is over, whatever
+//! its status. Memory and the programs' own state are kept by the engine
+//! between runs, as in a [`Context`] or a [`VM`], and every run starts on an
+//! empty operand stack.
+
+use crate::config::{Config, MAX_STACK_LIMIT, UnderflowPolicy};
+use crate::container::{Container, validate_bytecode};
+use crate::env::Environment;
+use crate::instructions;
+use crate::program::{CompiledProgram, Context};
+use crate::status::Status;
+use crate::vm::VM;
+use std::ffi::c_void;
+
+/// Limits an engine enforces; see `Config`.
+#[repr(C)]
+pub struct EngineConfig {
+    /// At most 65536 words.
+    pub stack_limit: usize,
+    /// Halt with `StackUnderflow` instead of reading missing operands as
+    /// zero.
+    pub trap_underflow: bool,
+}
+
+/// Storage of the program being executed. Either callback may be null:
+/// keys then read as zero, or writes are dropped.
+#[repr(C)]
+pub struct StorageCallbacks {
+    pub user_data: *mut c_void,
+    pub load: Option<unsafe extern "C" fn(user_data: *mut c_void, key: u8) -> u64>,
+    pub store: Option<unsafe extern "C" fn(user_data: *mut c_void, key: u8, value: u64)>,
+}
+
+#[repr(C)]
+pub struct ExecResult {
+    pub status: Status,
+    pub gas_used: u64,
+}
+
+/// Both engines, sharing one config.
+pub struct Engine {
+    ctx: Context,
+    vm: VM,
+    return_data: Vec<u8>,
+}
+
+/// Loaded bytecode, compiled or not.
+pub struct Program {
+    code: Code,
+    keys: Vec<u8>,
+    compiled: Option<CompiledProgram>,
+}
+
+enum Code {
+    Bytecode(Vec<u8>),
+    Container(Container),
+}
+
+impl Program {
+    fn new(code: Code) -> *mut Program {
+        let keys = match &code {
+            Code::Bytecode(code) => instructions::storage_keys(code),
+            Code::Container(container) => instructions::storage_keys(container.code()),
+        };
+        Box::into_raw(Box::new(Program {
+            code,
+            keys,
+            compiled: None,
+        }))
+    }
+}
+
+/// Views `len` bytes at `ptr` as a slice; null is only accepted with a
+/// zero length.
+unsafe fn bytes<'a>(ptr: *const u8, len: usize) -> Option<&'a [u8]> {
+    match (ptr.is_null(), len) {
+        (true, 0) => Some(&[]),
+        (true, _) => None,
+        (false, _) => Some(unsafe { std::slice::from_raw_parts(ptr, len) }),
+    }
+}
+
+/// Creates an engine, with the default config if `config` is null. Returns
+/// null if the config's stack limit is above 65536. Free the engine with
+/// `poc_engine_free`.
+///
+/// # Safety
+///
+/// `config` must be null or point to a valid `PocEngineConfig`.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn poc_engine_new(config: *const EngineConfig) -> *mut Engine {
+    let config = match unsafe { config.as_ref() } {
+        Some(config) if config.stack_limit > MAX_STACK_LIMIT => return std::ptr::null_mut(),
+        Some(config) => Config {
+            stack_limit: config.stack_limit,
+            underflow: if config.trap_underflow {
+                UnderflowPolicy::Trap
+            } else {
+                UnderflowPolicy::ZeroFill
+            },
+        },
+        None => Config::default(),
+    };
+    let mut vm = VM::default();
+    vm.set_config(config);
+    Box::into_raw(Box::new(Engine {
+        ctx: Context::new(config),
+        vm,
+        return_data: Vec::new(),
+    }))
+}
+
+/// # Safety
+///
+/// `engine` must be null or come from `poc_engine_new`, and not be used
+/// afterwards.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn poc_engine_free(engine: *mut Engine) {
+    if !engine.is_null() {
+        drop(unsafe { Box::from_raw(engine) });
+    }
+}
+
+/// Loads raw bytecode, which is validated and copied. Returns null if it has
+/// an undefined opcode or a truncated immediate, or if `code` is null and
+/// `len` is not 0. Free the program with `poc_program_free`.
+///
+/// # Safety
+///
+/// `code` must point to `len` readable bytes.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn poc_program_load(code: *const u8, len: usize) -> *mut Program {
+    match unsafe { bytes(code, len) } {
+        Some(code) if validate_bytecode(code).is_ok() => {
+            Program::new(Code::Bytecode(code.to_vec()))
+        }
+        _ => std::ptr::null_mut(),
+    }
+}
+
+/// Loads a container, which is validated and copied. Returns null if it is
+/// invalid.
+///
+/// # Safety
+///
+/// `bytes` must point to `len` readable bytes.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn poc_program_load_container(bytes: *const u8, len: usize) -> *mut Program {
+    let Some(bytes) = (unsafe { self::bytes(bytes, len) }) else {
+        return std::ptr::null_mut();
+    };
+    match Container::parse(bytes) {
+        Ok(container) => Program::new(Code::Container(container)),
+        Err(_) => std::ptr::null_mut(),
+    }
+}
+
+/// Compiles `program` with the config of `engine`; from then on it runs as
+/// machine code, on engines with the same config only. Returns false if an
+/// argument is null.
+///
+/// # Safety
+///
+/// `engine` and `program` must be null or valid.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn poc_program_compile(engine: *const Engine, program: *mut Program) -> bool {
+    let (Some(engine), Some(program)) = (unsafe { engine.as_ref() }, unsafe { program.as_mut() })
+    else {
+        return false;
+    };
+    let config = engine.ctx.config();
+    program.compiled = Some(match &program.code {
+        Code::Bytecode(code) => {
+            CompiledProgram::new(code, config).expect("loaded bytecode is valid")
+        }
+        Code::Container(container) => CompiledProgram::from_container(container, config),
+    });
+    true
+}
+
+/// # Safety
+///
+/// `program` must be null or come from `poc_program_load` or
+/// `poc_program_load_container`, and not be used afterwards.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn poc_program_free(program: *mut Program) {
+    if !program.is_null() {
+        drop(unsafe { Box::from_raw(program) });
+    }
+}
+
+/// Runs `program` on `engine` and stores how it ended in `result`. `env`
+/// and `storage` may be null for a default environment and zeroed storage.
+/// A fault in compiled code halts it with `Trapped`. Returns false, without
+/// running anything, if `engine`, `program` or `result` is null, `calldata`
+/// is null with a non-zero length, or the program was compiled with a
+/// different config.
+///
+/// # Safety
+///
+/// Every pointer must be null or valid, `calldata` spanning `calldata_len`
+/// bytes, and the callbacks must be safe to call with `user_data`.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn poc_execute(
+    engine: *mut Engine,
+    program: *const Program,
+    calldata: *const u8,
+    calldata_len: usize,
+    gas_limit: u64,
+    env: *const Environment,
+    storage: *const StorageCallbacks,
+    result: *mut ExecResult,
+) -> bool {
+    let (Some(engine), Some(program), Some(result)) = (
+        unsafe { engine.as_mut() },
+        unsafe { program.as_ref() },
+        unsafe { result.as_mut() },
+    ) else {
+        return false;
+    };
+    let Some(calldata) = (unsafe { bytes(calldata, calldata_len) }) else {
+        return false;
+    };
+    let env = unsafe { env.as_ref() }.copied().unwrap_or_default();
+    let storage = unsafe { storage.as_ref() };
+    if let Some(compiled) = &program.compiled
+        && compiled.config() != engine.ctx.config()
+    {
+        return false;
+    }
+
+    let loaded: Vec<(u8, u64)> = program
+        .keys
+        .iter()
+        .map(|&key| {
+            let value = match storage.and_then(|s| s.load.map(|load| (s, load))) {
+                Some((s, load)) => unsafe { load(s.user_data, key) },
+                None => 0,
+            };
+            (key, value)
+        })
+        .collect();
+
+    let (status, gas_used, stored) = match &program.compiled {
+        Some(compiled) => {
+            let ctx = &mut engine.ctx;
+            for &(key, value) in &loaded {
+                ctx.storage_mut()[key as usize] = value;
+            }
+            ctx.calldata = calldata.to_vec();
+            ctx.env = env;
+            ctx.gas_limit = gas_limit;
+            let (status, gas_used) = match compiled.run_guarded(ctx) {
+                Ok(outcome) => (outcome.status, outcome.gas_used),
+                Err(_) => (Status::Trapped, gas_limit),
+            };
+            engine.return_data = ctx.host().return_data().to_vec();
+            let stored: Vec<u64> = loaded
+                .iter()
+                .map(|&(key, _)| ctx.storage()[key as usize])
+                .collect();
+            (status, gas_used, stored)
+        }
+        None => {
+            let vm = &mut engine.vm;
+            for &(key, value) in &loaded {
+                vm.storage_mut().insert(key, value);
+            }
+            vm.set_gas_limit(gas_limit);
+            let status = match &program.code {
+                Code::Bytecode(code) => vm.interpret(code, calldata, &env),
+                Code::Container(container) => vm.interpret_container(container, calldata, &env),
+            };
+            engine.return_data = vm.return_data().to_vec();
+            let stored: Vec<u64> = loaded
+                .iter()
+                .map(|(key, _)| vm.storage().get(key).copied().unwrap_or(0))
+                .collect();
+            (status, vm.gas_used(), stored)
+        }
+    };
+
+    if let Some(store) = storage.and_then(|s| s.store.map(|store| (s, store))) {
+        for (&(key, before), after) in loaded.iter().zip(stored) {
+            if after != before {
+                unsafe { (store.1)(store.0.user_data, key, after) };
+            }
+        }
+    }
+    *result = ExecResult { status, gas_used };
+    true
+}
+
+/// Output of the last run on `engine`, set by RETURN, valid until the next
+/// run or until the engine is freed. Its length is stored in `len`. Returns
+/// null with a length of 0 if `engine` is null, and does nothing if `len` is
+/// null.
+///
+/// # Safety
+///
+/// `engine` and `len` must be null or valid, `len` pointing to a writable
+/// `size_t`.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn poc_engine_return_data(
+    engine: *const Engine,
+    len: *mut usize,
+) -> *const u8 {
+    let Some(len) = (unsafe { len.as_mut() }) else {
+        return std::ptr::null();
+    };
+    match unsafe { engine.as_ref() } {
+        Some(engine) => {
+            *len = engine.return_data.len();
+            engine.return_data.as_ptr()
+        }
+        None => {
+            *len = 0;
+            std::ptr::null()
+        }
+    }
+}
@@ -0,0 +1,55 @@
+//! Gas schedule shared by both engines.
+//!
+//! Every opcode has a static cost charged when it executes; opcodes whose
+//! price depends on their operands add a dynamic cost through the hooks below.
+
+use crate::instructions::opcodes::*;
+
+/// Price of each byte of an EXP exponent.
+pub const EXP_BYTE_COST: u64 = 50;
+
+/// Price of each byte of data attached to a log record.
+pub const LOG_DATA_BYTE_COST: u64 = 8;
+
+/// Price of each 32-byte word hashed by KECCAK256.
+pub const KECCAK_WORD_COST: u64 = 6;
+
+/// Static cost of executing `op`.
+pub fn static_cost(op: u8) -> u64 {
+    match op {
+        STOP | RETURN => 0,
+        BEGINSUB => 1,
+        RETSUB => 5,
+        CALLSUB => 10,
+        CALLDATASIZE | POP | CALLER | ADDRESS | CALLVALUE | NUMBER | TIMESTAMP | CHAINID | GAS
+        | PC | DATASIZE => 2,
+        DUP1..=DUP16 | SWAP1..=SWAP16 => 3,
+        PUSH | DUP | SWAP | ADD | SUB | EQ | LT | GT | SLT | SGT | AND | OR | XOR | NOT
+        | ISZERO | BYTE | SHL | SHR | SAR | CALLDATALOAD | CALLDATACOPY | MLOAD | MSTORE
+        | DATALOAD | DATALOADN | DATACOPY => 3,
+        MUL | DIV | MOD | SDIV | SMOD | SIGNEXTEND => 5,
+        ADDMOD | MULMOD => 8,
+        EXP => 10,
+        KECCAK256 => 30,
+        SLOAD | SSTORE | TLOAD | TSTORE | CALL => 100,
+        LOG0..=LOG4 => 375 * (1 + (op - LOG0) as u64),
+        _ => 0,
+    }
+}
+
+/// Dynamic cost of LOGn: `LOG_DATA_BYTE_COST` for every byte of data.
+pub fn log_cost(size: u64) -> u64 {
+    LOG_DATA_BYTE_COST.saturating_mul(size)
+}
+
+/// Dynamic cost of KECCAK256: `KECCAK_WORD_COST` for every started 32-byte
+/// word of input.
+pub fn keccak_cost(size: u64) -> u64 {
+    KECCAK_WORD_COST.saturating_mul(size.div_ceil(32))
+}
+
+/// Dynamic cost of EXP: `EXP_BYTE_COST` for every byte of the exponent.
+pub fn exp_cost(exponent: u64) -> u64 {
+    let bytes = (64 - exponent.leading_zeros() as u64).div_ceil(8);
+    EXP_BYTE_COST * bytes
+}
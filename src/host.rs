@@ -0,0 +1,354 @@
+//! Host side of JIT execution: the registry of deployed programs, each with
+//! its own storage and transient storage, and the `extern "C"` entry points
+//! compiled code uses to CALL into another program, RETURN data and emit logs.
+
+use crate::config::Config;
+use crate::container::{ContainerError, validate_bytecode};
+use crate::env::Environment;
+use crate::gas;
+use crate::jit::{CodeMap, JitFn, JitResult, OperandStack, STACK_RED_ZONE, make_jit};
+use crate::runtime::{self, CALL_DEPTH_LIMIT, Log, MEMORY_SIZE, STORAGE_SLOTS};
+use crate::status::Status;
+use crate::trap::{self, Region, Trap};
+use dynasmrt::{AssemblyOffset, ExecutableBuffer};
+
+struct Deployed {
+    buf: ExecutableBuffer,
+    map: CodeMap,
+    storage: Vec<u64>,
+    transient: Vec<u64>,
+}
+
+impl Deployed {
+    fn entry(&self) -> JitFn {
+        unsafe { std::mem::transmute(self.buf.ptr(AssemblyOffset(0))) }
+    }
+}
+
+/// Programs callable through CALL and the outcome of the last run.
+/// Compiled code receives a `*mut Host` and must be run with the same host
+/// it may call back into. Deployed programs are compiled with the host's
+/// config.
+pub struct Host {
+    config: Config,
+    programs: Vec<Deployed>,
+    /// Transient storage of the top-level program.
+    transient: Vec<u64>,
+    /// Operand stack of the top-level program and its depth after the last
+    /// run.
+    stack: Vec<u64>,
+    stack_len: usize,
+    return_data: Vec<u8>,
+    logs: Vec<Log>,
+    depth: usize,
+}
+
+impl Default for Host {
+    fn default() -> Self {
+        Host::new(Config::default())
+    }
+}
+
+impl Host {
+    pub fn new(config: Config) -> Self {
+        Self {
+            config,
+            programs: Vec::new(),
+            transient: vec![0; STORAGE_SLOTS],
+            stack: vec![0; config.stack_limit + STACK_RED_ZONE],
+            stack_len: 0,
+            return_data: Vec::new(),
+            logs: Vec::new(),
+            depth: 0,
+        }
+    }
+
+    /// Compiles `code`, once it passes [`validate_bytecode`], and registers
+    /// it with zeroed storage. Returns its id.
+    pub fn deploy(&mut self, code: &[u8]) -> Result<u64, ContainerError> {
+        validate_bytecode(code)?;
+        let (buf, map) = make_jit(code, &self.config);
+        self.programs.push(Deployed {
+            buf,
+            map,
+            storage: vec![0; STORAGE_SLOTS],
+            transient: vec![0; STORAGE_SLOTS],
+        });
+        Ok((self.programs.len() - 1) as u64)
+    }
+
+    pub fn config(&self) -> &Config {
+        &self.config
+    }
+
+    /// Storage of a deployed program.
+    pub fn storage(&self, id: u64) -> &[u64] {
+        &self.programs[id as usize].storage
+    }
+
+    /// Runs compiled code as a top-level program against this host. The
+    /// outcome of the previous run is cleared, logs are only kept if this one
+    /// succeeds and transient storage is cleared either way.
+    ///
+    /// # Safety
+    ///
+    /// `entry` must have been compiled with this host's config and `memory`
+    /// must hold `MEMORY_SIZE` bytes, as [`JitFn`] requires.
+    /// [`CompiledProgram`](crate::program::CompiledProgram) ensures both.
+    pub unsafe fn run(
+        &mut self,
+        entry: JitFn,
+        storage: &mut [u64],
+        memory: &mut [u8],
+        calldata: &[u8],
+        gas_limit: u64,
+        env: &Environment,
+    ) -> JitResult {
+        self.return_data.clear();
+        self.logs.clear();
+        let transient = self.transient.as_mut_ptr();
+        let mut stack = OperandStack {
+            end: self.stack[self.config.stack_limit..].as_mut_ptr(),
+            len: 0,
+        };
+        let result = unsafe {
+            entry(
+                storage.as_mut_ptr(),
+                storage.len(),
+                memory.as_mut_ptr(),
+                calldata.as_ptr(),
+                calldata.len(),
+                self,
+                gas_limit,
+                env,
+                transient,
+                &mut stack,
+            )
+        };
+        self.stack_len = stack.len;
+        if result.status() != Status::Stopped {
+            self.logs.clear();
+        }
+        self.transient.fill(0);
+        for program in &mut self.programs {
+            program.transient.fill(0);
+        }
+        result
+    }
+
+    /// Like [`Host::run`], for the code in `buf`, but a fault in it or in any
+    /// deployed program it calls is returned as a [`Trap`] instead of killing
+    /// the process. Every JIT frame halts with `Status::Trapped`, so the
+    /// storage writes of callees are rolled back as for any failed call,
+    /// while those of the top-level program are kept.
+    ///
+    /// # Safety
+    ///
+    /// As for [`Host::run`], with `buf` and `map` from the same call to
+    /// [`make_jit`] or `make_jit_container`.
+    #[allow(clippy::too_many_arguments)]
+    pub unsafe fn run_guarded(
+        &mut self,
+        buf: &ExecutableBuffer,
+        map: &CodeMap,
+        storage: &mut [u64],
+        memory: &mut [u8],
+        calldata: &[u8],
+        gas_limit: u64,
+        env: &Environment,
+    ) -> Result<JitResult, Trap> {
+        let entry: JitFn = unsafe { std::mem::transmute(buf.ptr(AssemblyOffset(0))) };
+        // the handler looks programs up while `run` holds the host mutably;
+        // deploying is not possible from compiled code, so they stay put
+        let programs: *const [Deployed] = &self.programs[..];
+        let locate = |address: usize| {
+            let top = Region {
+                buf,
+                map,
+                program: None,
+            };
+            if top.contains(address) {
+                return Some(top);
+            }
+            let programs = unsafe { &*programs };
+            programs
+                .iter()
+                .enumerate()
+                .map(|(id, p)| Region {
+                    buf: &p.buf,
+                    map: &p.map,
+                    program: Some(id as u64),
+                })
+                .find(|region| region.contains(address))
+        };
+        let (result, trap) = trap::guard(&locate, || unsafe {
+            self.run(entry, storage, memory, calldata, gas_limit, env)
+        });
+        match trap {
+            Some(trap) => Err(trap),
+            None => Ok(result),
+        }
+    }
+
+    /// Operand stack left by the last run, bottom first.
+    pub fn stack(&self) -> Vec<u64> {
+        let end = self.config.stack_limit;
+        let items = &self.stack[end - self.stack_len..end];
+        items.iter().rev().copied().collect()
+    }
+
+    /// Output of the last run, set by RETURN.
+    pub fn return_data(&self) -> &[u8] {
+        &self.return_data
+    }
+
+    /// Logs emitted by the last successful run.
+    pub fn logs(&self) -> &[Log] {
+        &self.logs
+    }
+}
+
+/// Result of [`jit_call`], returned in RAX:RDX.
+#[repr(C)]
+pub(crate) struct CallResult {
+    success: u64,
+    gas_used: u64,
+}
+
+// The entry points below are only called from JIT code with the host and the
+// memory (`MEMORY_SIZE` bytes) it was entered with. Nested calls re-enter the
+// host, so each one re-borrows it from the raw pointer instead of holding a
+// reference across the callee's run.
+
+/// Executes a CALL. `args` points at the six operands on the caller's operand
+/// stack, top first: gas limit, program id, input offset and size, output
+/// offset and size. `env` is the caller's environment, from which the
+/// callee's is derived. Mirrors `VM::call`: a failed callee is charged its whole
+/// gas limit and storage and transient storage writes and logs made since the
+/// call are rolled back. `success` is 1 if the callee stopped, 0 if it failed
+/// and 2 if it trapped, in which case the caller halts with it.
+pub(crate) unsafe extern "C" fn jit_call(
+    host: *mut Host,
+    memory: *mut u8,
+    args: *const u64,
+    env: *const Environment,
+) -> CallResult {
+    let args = unsafe { std::slice::from_raw_parts(args, 6) };
+    let (gas_limit, id) = (args[0], args[1]);
+    let callee_env = unsafe { &*env }.for_callee(id);
+    let memory = unsafe { std::slice::from_raw_parts_mut(memory, MEMORY_SIZE) };
+    let failed = |gas_used| CallResult {
+        success: 0,
+        gas_used,
+    };
+
+    let (entry, storage, transient, snapshot, logs_len, stack_limit) = {
+        let host = unsafe { &mut *host };
+        let Some(program) = usize::try_from(id)
+            .ok()
+            .and_then(|id| host.programs.get_mut(id))
+        else {
+            return failed(0);
+        };
+        if host.depth >= CALL_DEPTH_LIMIT {
+            return failed(0);
+        }
+        let entry = program.entry();
+        let storage = program.storage.as_mut_ptr();
+        let transient = program.transient.as_mut_ptr();
+        let snapshot: Vec<(Vec<u64>, Vec<u64>)> = host
+            .programs
+            .iter()
+            .map(|p| (p.storage.clone(), p.transient.clone()))
+            .collect();
+        host.depth += 1;
+        host.return_data.clear();
+        let stack_limit = host.config.stack_limit;
+        (
+            entry,
+            storage,
+            transient,
+            snapshot,
+            host.logs.len(),
+            stack_limit,
+        )
+    };
+
+    let input = runtime::memory_slice(memory, args[2], args[3]).to_vec();
+    let mut callee_memory = vec![0u8; MEMORY_SIZE];
+    let mut callee_stack = vec![0u64; stack_limit + STACK_RED_ZONE];
+    let mut stack = OperandStack {
+        end: callee_stack[stack_limit..].as_mut_ptr(),
+        len: 0,
+    };
+    let result = unsafe {
+        entry(
+            storage,
+            STORAGE_SLOTS,
+            callee_memory.as_mut_ptr(),
+            input.as_ptr(),
+            input.len(),
+            host,
+            gas_limit,
+            &callee_env,
+            transient,
+            &mut stack,
+        )
+    };
+
+    let host = unsafe { &mut *host };
+    host.depth -= 1;
+    let output = std::mem::take(&mut host.return_data);
+    if result.status() == Status::Stopped {
+        runtime::copy_return_data(memory, args[4], args[5], &output);
+        CallResult {
+            success: 1,
+            gas_used: result.gas_used,
+        }
+    } else {
+        for (program, (storage, transient)) in host.programs.iter_mut().zip(snapshot) {
+            program.storage.copy_from_slice(&storage);
+            program.transient.copy_from_slice(&transient);
+        }
+        host.logs.truncate(logs_len);
+        CallResult {
+            success: if result.status() == Status::Trapped {
+                2
+            } else {
+                0
+            },
+            gas_used: gas_limit,
+        }
+    }
+}
+
+/// Executes a RETURN of `memory[offset..offset + size]`.
+pub(crate) unsafe extern "C" fn jit_return(
+    host: *mut Host,
+    memory: *const u8,
+    offset: u64,
+    size: u64,
+) {
+    let host = unsafe { &mut *host };
+    let memory = unsafe { std::slice::from_raw_parts(memory, MEMORY_SIZE) };
+    host.return_data = runtime::memory_slice(memory, offset, size).to_vec();
+}
+
+/// Executes LOGn. `args` points at the operands on the operand stack, top
+/// first: memory offset and size, then `topics` topics. Returns the dynamic
+/// gas to charge.
+pub(crate) unsafe extern "C" fn jit_log(
+    host: *mut Host,
+    memory: *const u8,
+    args: *const u64,
+    topics: usize,
+) -> u64 {
+    let host = unsafe { &mut *host };
+    let memory = unsafe { std::slice::from_raw_parts(memory, MEMORY_SIZE) };
+    let args = unsafe { std::slice::from_raw_parts(args, 2 + topics) };
+    host.logs.push(Log {
+        topics: args[2..].to_vec(),
+        data: runtime::memory_slice(memory, args[0], args[1]).to_vec(),
+    });
+    gas::log_cost(args[1])
+}
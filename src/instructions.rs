@@ -15,5 +15,218 @@ pub mod opcodes {
     pub const XOR: u8 = 0x0E;
     pub const DUP: u8 = 0x0F;
     pub const SWAP: u8 = 0x10;
+    pub const CALLDATALOAD: u8 = 0x11;
+    pub const CALLDATASIZE: u8 = 0x12;
+    pub const CALLDATACOPY: u8 = 0x13;
+    pub const MLOAD: u8 = 0x14;
+    pub const MSTORE: u8 = 0x15;
+    pub const SDIV: u8 = 0x16;
+    pub const SMOD: u8 = 0x17;
+    pub const SLT: u8 = 0x18;
+    pub const SGT: u8 = 0x19;
+    pub const SIGNEXTEND: u8 = 0x1A;
+    pub const SHL: u8 = 0x1B;
+    pub const SHR: u8 = 0x1C;
+    pub const SAR: u8 = 0x1D;
+    pub const NOT: u8 = 0x1E;
+    pub const ISZERO: u8 = 0x1F;
+    pub const BYTE: u8 = 0x20;
+    pub const ADDMOD: u8 = 0x21;
+    pub const MULMOD: u8 = 0x22;
+    pub const EXP: u8 = 0x23;
+    pub const POP: u8 = 0x24;
+    // DUPn copies the n-th item (DUP1 is the top); SWAPn exchanges the top
+    // with the item n below it. The n-th variant is `DUP1 + n - 1`.
+    pub const DUP1: u8 = 0x30;
+    pub const DUP16: u8 = 0x3F;
+    pub const SWAP1: u8 = 0x40;
+    pub const SWAP16: u8 = 0x4F;
+    // EIP-2315 style subroutines: CALLSUB takes a 2-byte big-endian target
+    // that must point at a BEGINSUB.
+    pub const BEGINSUB: u8 = 0x25;
+    pub const CALLSUB: u8 = 0x26;
+    pub const RETSUB: u8 = 0x27;
+    // CALL pops gas, program id, input offset/size and output offset/size
+    // and pushes 1 on success; RETURN halts with a memory range as output.
+    pub const CALL: u8 = 0x28;
+    pub const RETURN: u8 = 0x29;
+    // LOGn pops a memory offset and size followed by n topics.
+    pub const LOG0: u8 = 0x2A;
+    pub const LOG4: u8 = 0x2E;
+    // KECCAK256 pops a memory offset and size and pushes the low 64 bits of
+    // the hash, i.e. the 256-bit EVM word truncated to our word width.
+    pub const KECCAK256: u8 = 0x2F;
+    // Execution environment; GAS pushes the gas left after its own cost and
+    // PC its own offset in the code.
+    pub const CALLER: u8 = 0x50;
+    pub const ADDRESS: u8 = 0x51;
+    pub const CALLVALUE: u8 = 0x52;
+    pub const NUMBER: u8 = 0x53;
+    pub const TIMESTAMP: u8 = 0x54;
+    pub const CHAINID: u8 = 0x55;
+    pub const GAS: u8 = 0x56;
+    pub const PC: u8 = 0x57;
+    // Transient storage: like SLOAD/SSTORE with a 1-byte key immediate, but
+    // cleared when the top-level run ends.
+    pub const TLOAD: u8 = 0x58;
+    pub const TSTORE: u8 = 0x59;
+    // Read-only data section of a container: DATALOADN takes a 2-byte
+    // big-endian offset immediate, words past the end read as zero.
+    pub const DATALOAD: u8 = 0x5A;
+    pub const DATALOADN: u8 = 0x5B;
+    pub const DATASIZE: u8 = 0x5C;
+    pub const DATACOPY: u8 = 0x5D;
     pub const STOP: u8 = 0xFF;
 }
+
+use opcodes::*;
+
+/// Whether `op` is an instruction both engines implement.
+pub fn is_defined(op: u8) -> bool {
+    matches!(
+        op,
+        SLOAD..=KECCAK256 | DUP1..=DUP16 | SWAP1..=SWAP16 | CALLER..=DATACOPY | STOP
+    )
+}
+
+/// Name of `op`, or `None` if it is not defined.
+pub fn mnemonic(op: u8) -> Option<String> {
+    let name = match op {
+        DUP1..=DUP16 => return Some(format!("DUP{}", op - DUP1 + 1)),
+        SWAP1..=SWAP16 => return Some(format!("SWAP{}", op - SWAP1 + 1)),
+        LOG0..=LOG4 => return Some(format!("LOG{}", op - LOG0)),
+        SLOAD => "SLOAD",
+        SSTORE => "SSTORE",
+        PUSH => "PUSH",
+        ADD => "ADD",
+        SUB => "SUB",
+        MUL => "MUL",
+        DIV => "DIV",
+        MOD => "MOD",
+        EQ => "EQ",
+        LT => "LT",
+        GT => "GT",
+        AND => "AND",
+        OR => "OR",
+        XOR => "XOR",
+        DUP => "DUP",
+        SWAP => "SWAP",
+        CALLDATALOAD => "CALLDATALOAD",
+        CALLDATASIZE => "CALLDATASIZE",
+        CALLDATACOPY => "CALLDATACOPY",
+        MLOAD => "MLOAD",
+        MSTORE => "MSTORE",
+        SDIV => "SDIV",
+        SMOD => "SMOD",
+        SLT => "SLT",
+        SGT => "SGT",
+        SIGNEXTEND => "SIGNEXTEND",
+        SHL => "SHL",
+        SHR => "SHR",
+        SAR => "SAR",
+        NOT => "NOT",
+        ISZERO => "ISZERO",
+        BYTE => "BYTE",
+        ADDMOD => "ADDMOD",
+        MULMOD => "MULMOD",
+        EXP => "EXP",
+        POP => "POP",
+        BEGINSUB => "BEGINSUB",
+        CALLSUB => "CALLSUB",
+        RETSUB => "RETSUB",
+        CALL => "CALL",
+        RETURN => "RETURN",
+        KECCAK256 => "KECCAK256",
+        CALLER => "CALLER",
+        ADDRESS => "ADDRESS",
+        CALLVALUE => "CALLVALUE",
+        NUMBER => "NUMBER",
+        TIMESTAMP => "TIMESTAMP",
+        CHAINID => "CHAINID",
+        GAS => "GAS",
+        PC => "PC",
+        TLOAD => "TLOAD",
+        TSTORE => "TSTORE",
+        DATALOAD => "DATALOAD",
+        DATALOADN => "DATALOADN",
+        DATASIZE => "DATASIZE",
+        DATACOPY => "DATACOPY",
+        STOP => "STOP",
+        _ => return None,
+    };
+    Some(name.to_string())
+}
+
+/// Number of immediate bytes following `op`.
+pub fn immediate_len(op: u8) -> usize {
+    match op {
+        PUSH | SLOAD | SSTORE | TLOAD | TSTORE => 1,
+        CALLSUB | DATALOADN => 2,
+        _ => 0,
+    }
+}
+
+/// Number of items `op` pops and pushes. DUP and DUPn read without popping
+/// and SWAP/SWAPn rearrange in place, so they pop nothing.
+pub fn stack_effect(op: u8) -> (usize, usize) {
+    match op {
+        PUSH
+        | SLOAD
+        | TLOAD
+        | DUP
+        | DUP1..=DUP16
+        | CALLDATASIZE
+        | CALLER..=PC
+        | DATALOADN
+        | DATASIZE => (0, 1),
+        SSTORE | TSTORE | POP => (1, 0),
+        NOT | ISZERO | CALLDATALOAD | MLOAD | DATALOAD => (1, 1),
+        ADD | SUB | MUL | DIV | MOD | EQ | LT | GT | AND | OR | XOR | SDIV | SMOD | SLT | SGT
+        | SIGNEXTEND | SHL | SHR | SAR | BYTE | EXP | KECCAK256 => (2, 1),
+        MSTORE | RETURN => (2, 0),
+        ADDMOD | MULMOD => (3, 1),
+        CALLDATACOPY | DATACOPY => (3, 0),
+        CALL => (6, 1),
+        LOG0..=LOG4 => (2 + (op - LOG0) as usize, 0),
+        _ => (0, 0),
+    }
+}
+
+/// Number of items that must be on the stack for `op` to run without
+/// underflowing: the items it pops, or for DUPn and SWAPn the deepest item
+/// it reaches.
+pub fn required_depth(op: u8) -> usize {
+    match op {
+        DUP => 1,
+        SWAP => 2,
+        DUP1..=DUP16 => (op - DUP1 + 1) as usize,
+        SWAP1..=SWAP16 => (op - SWAP1 + 2) as usize,
+        _ => stack_effect(op).0,
+    }
+}
+
+/// Keys of the SLOAD and SSTORE instructions in `code`, sorted and without
+/// duplicates.
+pub fn storage_keys(code: &[u8]) -> Vec<u8> {
+    let mut used = [false; 256];
+    let mut pc = 0;
+    while pc < code.len() {
+        if let (SLOAD | SSTORE, Some(&key)) = (code[pc], code.get(pc + 1)) {
+            used[key as usize] = true;
+        }
+        pc += 1 + immediate_len(code[pc]);
+    }
+    (0..=255).filter(|&key| used[key as usize]).collect()
+}
+
+/// Marks every pc holding a BEGINSUB opcode (as opposed to an immediate byte
+/// that happens to have the same value), i.e. the valid CALLSUB targets.
+pub fn subroutine_entries(code: &[u8]) -> Vec<bool> {
+    let mut entries = vec![false; code.len()];
+    let mut pc = 0;
+    while pc < code.len() {
+        entries[pc] = code[pc] == BEGINSUB;
+        pc += 1 + immediate_len(code[pc]);
+    }
+    entries
+}
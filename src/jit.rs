@@ -1,37 +1,352 @@
-use crate::instructions::opcodes::*;
-use dynasmrt::{DynasmApi, DynasmLabelApi, ExecutableBuffer, dynasm};
-
-/// Builds a JIT function of signature `fn(*mut u64)`
-/// the JIT code uses its first argument (in RDI) as the base pointer
-/// to an array of u64 slots (key*8 indexing).
-pub fn make_jit(code: &[u8]) -> ExecutableBuffer {
-    use dynasmrt::x64::Assembler;
+use crate::config::{Config, UnderflowPolicy};
+use crate::container::Container;
+use crate::env::Environment;
+use crate::gas;
+use crate::host::{self, Host};
+use crate::instructions::{self, opcodes::*};
+use crate::runtime::{self, MEMORY_SIZE, RETURN_STACK_LIMIT};
+use crate::status::Status;
+use dynasmrt::x64::Assembler;
+use dynasmrt::{DynamicLabel, DynasmApi, DynasmLabelApi, ExecutableBuffer, dynasm};
+use std::ops::Range;
+
+/// Largest offset at which an 8-byte memory access needs no clipping.
+const MEMORY_FAST_LIMIT: i32 = MEMORY_SIZE as i32 - 8;
+
+// Frame layout on the native stack, relative to R12.
+const FRAME_GAS: i32 = 0;
+const FRAME_GAS_LIMIT: i32 = 8;
+const FRAME_HOST: i32 = 16;
+const FRAME_ENV: i32 = 24;
+const FRAME_TRANSIENT: i32 = 32;
+const FRAME_STACK: i32 = 40;
+const FRAME_STACK_BASE: i32 = 48;
+const FRAME_STACK_FLOOR: i32 = 56;
+const FRAME_RS_TOP: i32 = 64;
+const FRAME_RS_BASE: i32 = 72;
+const FRAME_SIZE: i32 = FRAME_RS_BASE + RETURN_STACK_LIMIT as i32 * 8;
+
+// With the six saved registers and the return address on top, this leaves
+// RSP 16-byte aligned for the whole run, as helper calls require.
+const _: () = assert!(FRAME_SIZE % 16 == 8);
+
+const STACK_END: i32 = std::mem::offset_of!(OperandStack, end) as i32;
+const STACK_LEN: i32 = std::mem::offset_of!(OperandStack, len) as i32;
+
+/// Offset of the arguments passed on the caller's stack (the seventh onward)
+/// from R12: past the frame, the six saved registers and the return address.
+const STACK_ARGS: i32 = FRAME_SIZE + 7 * 8;
+
+/// Value returned by compiled code in RAX:RDX.
+#[repr(C)]
+pub struct JitResult {
+    pub status: u64,
+    pub gas_used: u64,
+}
+
+impl JitResult {
+    pub fn status(&self) -> Status {
+        Status::from_raw(self.status)
+    }
+}
+
+/// Zeroed words an operand stack needs past its end. Under
+/// `UnderflowPolicy::ZeroFill` missing operands are read from there; no
+/// instruction pops more than six.
+pub const STACK_RED_ZONE: usize = 6;
+
+/// Operand stack of a compiled program, allocated by the caller with room
+/// for `stack_limit` words below `end` and [`STACK_RED_ZONE`] zeroed words
+/// from `end` on, which compiled code leaves zeroed. It starts with an empty
+/// stack and grows it down from `end`, so the items are the `len` words
+/// below it, top first. `len` is written when the code returns, whatever
+/// the status.
+#[repr(C)]
+pub struct OperandStack {
+    pub end: *mut u64,
+    pub len: usize,
+}
+
+/// Entry point of a compiled program: storage slots and their number,
+/// memory, call data and its length, the host it can call back into, the
+/// gas limit, the execution environment, the transient storage slots and
+/// the operand stack. The code halts with `StorageTooSmall` before running
+/// if a key it uses does not fit the storage; every other argument is
+/// trusted, so calling it is unsafe.
+/// [`CompiledProgram`](crate::program::CompiledProgram) is the safe way to
+/// run compiled code.
+///
+/// # Safety
+///
+/// The function must come from [`make_jit`] or [`make_jit_container`] with
+/// the config of the host, and memory must hold `MEMORY_SIZE` bytes. The
+/// transient storage must hold `STORAGE_SLOTS` slots and the operand stack
+/// must be laid out as [`OperandStack`] describes.
+pub type JitFn = unsafe extern "C" fn(
+    *mut u64,
+    usize,
+    *mut u8,
+    *const u8,
+    usize,
+    *mut Host,
+    u64,
+    *const Environment,
+    *mut u64,
+    *mut OperandStack,
+) -> JitResult;
+
+/// Where the native code of each instruction lies in a compiled buffer, so
+/// a fault inside it can be traced back to the bytecode.
+#[derive(Clone, Debug)]
+pub struct CodeMap {
+    /// (bytecode pc, native offset) of every instruction, in code order.
+    pcs: Vec<(usize, usize)>,
+    /// Native offsets of the instructions: everything but the prologue and
+    /// the shared exit paths, which run before or after the frame is usable.
+    body: Range<usize>,
+    /// Native offset of the stub that halts the frame with `Trapped`.
+    trap: usize,
+}
+
+impl CodeMap {
+    /// Bytecode pc of the instruction whose native code contains `offset`,
+    /// or `None` if the offset lies outside the instructions.
+    pub fn pc_at(&self, offset: usize) -> Option<usize> {
+        if !self.body.contains(&offset) {
+            return None;
+        }
+        let i = self.pcs.partition_point(|&(_, start)| start <= offset);
+        Some(self.pcs[i - 1].0)
+    }
+
+    /// (bytecode pc, native offset) of every instruction, sorted by both.
+    pub fn pcs(&self) -> &[(usize, usize)] {
+        &self.pcs
+    }
+
+    /// Bytecode pc and native code range of every instruction, in code order.
+    pub fn instructions(&self) -> impl Iterator<Item = (usize, Range<usize>)> + '_ {
+        let ends = self.pcs.iter().skip(1).map(|&(_, start)| start);
+        self.pcs
+            .iter()
+            .zip(ends.chain([self.body.end]))
+            .map(|(&(pc, start), end)| (pc, start..end))
+    }
+
+    /// Native offset to resume a faulting frame at: it consumes all gas and
+    /// returns `Status::Trapped` through the normal epilogue.
+    pub(crate) fn trap_offset(&self) -> usize {
+        self.trap
+    }
+}
+
+/// Builds a JIT function of signature [`JitFn`], along with the map from
+/// its native code back to the bytecode.
+/// Storage is an array of u64 slots (key*8 indexing) kept in RBX, memory is
+/// `MEMORY_SIZE` bytes kept in R13 and the call data lives in R14/R15.
+/// RBP points at the top of the operand stack. R12 points at a frame on the
+/// native stack holding the gas counter and limit, the host, the
+/// environment, the transient storage slots, the operand stack with its
+/// bottom and the lowest address it may reach, and the return stack. Static
+/// gas is summed per basic block at compile time and charged, and checked
+/// against the limit, when the block is left.
+///
+/// The operand stack depth is tracked at compile time wherever it is known,
+/// i.e. outside subroutines and up to the first CALLSUB, so the bounds
+/// checks cost nothing there; elsewhere instructions compare RBP against the
+/// bottom of the stack before popping and against the floor before growing
+/// it. Under `UnderflowPolicy::ZeroFill` an instruction short of operands
+/// reads the missing ones from the red zone below the bottom and its result
+/// is then moved down to the bottom.
+pub fn make_jit(code: &[u8], config: &Config) -> (ExecutableBuffer, CodeMap) {
+    compile(code, &instructions::subroutine_entries(code), &[], config)
+}
+
+/// Builds a JIT function from a validated container, reusing the subroutine
+/// entries found during validation. The data section is emitted as a
+/// constant pool after the code and read with RIP-relative addressing, and
+/// the declared max stack height is the stack limit if it is below the
+/// config's.
+pub fn make_jit_container(container: &Container, config: &Config) -> (ExecutableBuffer, CodeMap) {
+    let stack_limit = config
+        .stack_limit
+        .min(container.max_stack_height() as usize);
+    compile(
+        container.code(),
+        container.subroutine_entries(),
+        container.data(),
+        &Config {
+            stack_limit,
+            ..*config
+        },
+    )
+}
+
+fn compile(
+    code: &[u8],
+    entries: &[bool],
+    data: &[u8],
+    config: &Config,
+) -> (ExecutableBuffer, CodeMap) {
+    let stack_limit = config.stack_limit;
+    let zero_fill = config.underflow == UnderflowPolicy::ZeroFill;
+    // the pool is followed by 8 zero bytes, so any word starting inside it
+    // can be loaded without clipping
+    let data_len = data.len() as i32;
+
     let mut ops = Assembler::new().unwrap();
 
     dynasm!(ops
         ; .arch x64
-        // prologue: save RBX and R12, move arg ptr (RDI) into RBX
+        // prologue: save callee-saved registers and pin the arguments
         ; push rbx
+        ; push rbp
         ; push r12
-        ; mov  rbx, rdi
+        ; push r13
+        ; push r14
+        ; push r15
+        ; sub  rsp, FRAME_SIZE
         ; mov  r12, rsp
+        ; mov  QWORD [r12 + FRAME_GAS], 0
+        ; mov  rax, [r12 + STACK_ARGS]
+        ; mov  [r12 + FRAME_GAS_LIMIT], rax
+        ; mov  [r12 + FRAME_HOST], r9
+        ; mov  rax, [r12 + STACK_ARGS + 8]
+        ; mov  [r12 + FRAME_ENV], rax
+        ; mov  rax, [r12 + STACK_ARGS + 16]
+        ; mov  [r12 + FRAME_TRANSIENT], rax
+        ; mov  rax, [r12 + STACK_ARGS + 24]
+        ; mov  [r12 + FRAME_STACK], rax
+        ; lea  rax, [r12 + FRAME_RS_BASE]
+        ; mov  [r12 + FRAME_RS_TOP], rax
+        ; mov  rbx, rdi
+        ; mov  r13, rdx
+        ; mov  r14, rcx
+        ; mov  r15, r8
+        ; mov  rax, [r12 + FRAME_STACK]
+        ; mov  rbp, [rax + STACK_END]
+        ; mov  [r12 + FRAME_STACK_BASE], rbp
+        ; mov  rax, rbp
+        ; mov  rdx, QWORD stack_limit.saturating_mul(8) as i64
+        ; sub  rax, rdx
+        ; jnc  >floor_set
+        ; xor  eax, eax
+        ; floor_set:
+        ; mov  [r12 + FRAME_STACK_FLOOR], rax
     );
 
+    // keys are immediates, so one check here covers every storage access
+    let storage_slots = instructions::storage_keys(code)
+        .last()
+        .map_or(0, |&key| key as usize + 1);
+    if storage_slots > 0 {
+        dynasm!(ops
+            ; cmp  rsi, storage_slots as i32
+            ; jb   ->storage_too_small
+        );
+    }
+
+    let labels: Vec<Option<DynamicLabel>> = entries
+        .iter()
+        .map(|&entry| entry.then(|| ops.new_dynamic_label()))
+        .collect();
+
+    let mut block_gas = 0u64;
+    // operand stack depth at `pc` if it is known at compile time
+    let mut depth = Some(0usize);
+    let mut pcs = Vec::new();
+    let body_start = ops.offset().0;
     let mut pc = 0;
     while pc < code.len() {
+        pcs.push((pc, ops.offset().0));
+        block_gas += gas::static_cost(code[pc]);
+        let (pops, pushes) = instructions::stack_effect(code[pc]);
+        let required = instructions::required_depth(code[pc]);
+        // whether the stack may hold fewer items than the instruction needs
+        let short = depth.is_none_or(|depth| depth < required);
+        if short && !zero_fill {
+            match depth {
+                Some(depth) if depth >= required => {}
+                Some(_) => {
+                    emit_charge(&mut ops, &mut block_gas);
+                    dynasm!(ops
+                        ; xor eax, eax
+                        ; jmp ->stack_underflow
+                    );
+                }
+                None => dynasm!(ops
+                    ; lea  rax, [rbp + required as i32 * 8]
+                    ; cmp  rax, [r12 + FRAME_STACK_BASE]
+                    ; jbe  >depth_ok
+                    ; mov  rax, QWORD block_gas as i64
+                    ; jmp  ->stack_underflow
+                    ; depth_ok:
+                ),
+            }
+        }
+        if pushes > pops {
+            let growth = pushes - pops;
+            match depth {
+                Some(depth) if depth + growth <= stack_limit => {}
+                Some(_) => {
+                    emit_charge(&mut ops, &mut block_gas);
+                    dynasm!(ops
+                        ; xor eax, eax
+                        ; jmp ->stack_overflow
+                    );
+                }
+                None => dynasm!(ops
+                    ; lea  rax, [rbp + -(growth as i32) * 8]
+                    ; cmp  rax, [r12 + FRAME_STACK_FLOOR]
+                    ; jae  >room_ok
+                    ; mov  rax, QWORD block_gas as i64
+                    ; jmp  ->stack_overflow
+                    ; room_ok:
+                ),
+            }
+        } else if short && zero_fill && pops > 0 && pushes > stack_limit {
+            // an instruction short of operands leaves its single result on
+            // an empty stack, which only overflows a zero limit
+            match depth {
+                Some(depth) if depth > 0 => {}
+                Some(_) => {
+                    emit_charge(&mut ops, &mut block_gas);
+                    dynasm!(ops
+                        ; xor eax, eax
+                        ; jmp ->stack_overflow
+                    );
+                }
+                None => dynasm!(ops
+                    ; cmp  rbp, [r12 + FRAME_STACK_BASE]
+                    ; jb   >room_ok
+                    ; mov  rax, QWORD block_gas as i64
+                    ; jmp  ->stack_overflow
+                    ; room_ok:
+                ),
+            }
+        }
+        // past the checks the stack can only come up short under ZeroFill
+        let short = short && zero_fill;
+        let padded = short && pops > 0 && code[pc] != POP;
+        depth = match code[pc] {
+            BEGINSUB | CALLSUB => None,
+            _ => depth.map(|depth| depth.saturating_sub(pops) + pushes),
+        };
         match code[pc] {
             PUSH => {
                 let val = code[pc + 1] as i32;
                 dynasm!(ops
-                    ; mov  rax, val
-                    ; push rax
+                    ; sub  rbp, 8
+                    ; mov  QWORD [rbp], val
                 );
                 pc += 2;
             }
             SSTORE => {
                 let key = code[pc + 1] as i32;
+                emit_charge(&mut ops, &mut block_gas);
                 dynasm!(ops
-                    ; pop  rax
+                    ; mov  rax, [rbp]
+                    ; add  rbp, 8
                     ; mov  [rbx + key * 8], rax
                 );
                 pc += 2;
@@ -40,41 +355,63 @@ pub fn make_jit(code: &[u8]) -> ExecutableBuffer {
                 let key = code[pc + 1] as i32;
                 dynasm!(ops
                     ; mov  rax, [rbx + key * 8]
-                    ; push rax
+                    ; sub  rbp, 8
+                    ; mov  [rbp], rax
+                );
+                pc += 2;
+            }
+            TSTORE => {
+                let key = code[pc + 1] as i32;
+                emit_charge(&mut ops, &mut block_gas);
+                dynasm!(ops
+                    ; mov  rdx, [r12 + FRAME_TRANSIENT]
+                    ; mov  rax, [rbp]
+                    ; add  rbp, 8
+                    ; mov  [rdx + key * 8], rax
+                );
+                pc += 2;
+            }
+            TLOAD => {
+                let key = code[pc + 1] as i32;
+                dynasm!(ops
+                    ; mov  rax, [r12 + FRAME_TRANSIENT]
+                    ; mov  rax, [rax + key * 8]
+                    ; sub  rbp, 8
+                    ; mov  [rbp], rax
                 );
                 pc += 2;
             }
+            // binary operators take the top item as their right-hand operand
+            // and overwrite the item below it with the result
             ADD => {
                 dynasm!(ops
-                    ; pop  rax
-                    ; pop  rdx
-                    ; add  rax, rdx
-                    ; push rax
+                    ; mov  rax, [rbp]
+                    ; add  rbp, 8
+                    ; add  [rbp], rax
                 );
                 pc += 1;
             }
             SUB => {
                 dynasm!(ops
-                    ; pop  rdx
-                    ; pop  rax
-                    ; sub  rax, rdx
-                    ; push rax
+                    ; mov  rax, [rbp]
+                    ; add  rbp, 8
+                    ; sub  [rbp], rax
                 );
                 pc += 1;
             }
             MUL => {
                 dynasm!(ops
-                    ; pop  rax
-                    ; pop  rdx
-                    ; imul rax, rdx
-                    ; push rax
+                    ; mov  rax, [rbp]
+                    ; add  rbp, 8
+                    ; imul rax, [rbp]
+                    ; mov  [rbp], rax
                 );
                 pc += 1;
             }
             DIV => {
                 dynasm!(ops
-                    ; pop  rcx  // divisor
-                    ; pop  rax  // dividend
+                    ; mov  rcx, [rbp]      // divisor
+                    ; mov  rax, [rbp + 8]  // dividend
                     ; test rcx, rcx
                     ; jnz  >safe_div
                     ; xor  rax, rax
@@ -83,14 +420,15 @@ pub fn make_jit(code: &[u8]) -> ExecutableBuffer {
                     ; xor  rdx, rdx
                     ; div  rcx
                     ; div_done:
-                    ; push rax
+                    ; add  rbp, 8
+                    ; mov  [rbp], rax
                 );
                 pc += 1;
             }
             MOD => {
                 dynasm!(ops
-                    ; pop  rcx  // divisor
-                    ; pop  rax  // dividend
+                    ; mov  rcx, [rbp]      // divisor
+                    ; mov  rax, [rbp + 8]  // dividend
                     ; test rcx, rcx
                     ; jnz  >safe_mod
                     ; xor  rax, rax
@@ -100,98 +438,831 @@ pub fn make_jit(code: &[u8]) -> ExecutableBuffer {
                     ; div  rcx
                     ; mov  rax, rdx
                     ; mod_done:
-                    ; push rax
+                    ; add  rbp, 8
+                    ; mov  [rbp], rax
                 );
                 pc += 1;
             }
             EQ => {
                 dynasm!(ops
-                    ; pop  rax
-                    ; pop  rdx
-                    ; cmp  rax, rdx
+                    ; mov  rax, [rbp]
+                    ; add  rbp, 8
+                    ; cmp  rax, [rbp]
                     ; sete al
                     ; movzx rax, al
-                    ; push rax
+                    ; mov  [rbp], rax
                 );
                 pc += 1;
             }
             LT => {
                 dynasm!(ops
-                    ; pop  rdx  // b
-                    ; pop  rax  // a
-                    ; cmp  rax, rdx
+                    ; mov  rdx, [rbp]      // b
+                    ; add  rbp, 8
+                    ; cmp  [rbp], rdx      // a
                     ; setb al
                     ; movzx rax, al
-                    ; push rax
+                    ; mov  [rbp], rax
                 );
                 pc += 1;
             }
             GT => {
                 dynasm!(ops
-                    ; pop  rdx  // b
-                    ; pop  rax  // a
-                    ; cmp  rax, rdx
+                    ; mov  rdx, [rbp]      // b
+                    ; add  rbp, 8
+                    ; cmp  [rbp], rdx      // a
                     ; seta al
                     ; movzx rax, al
-                    ; push rax
+                    ; mov  [rbp], rax
+                );
+                pc += 1;
+            }
+            SDIV => {
+                dynasm!(ops
+                    ; mov  rcx, [rbp]      // divisor
+                    ; mov  rax, [rbp + 8]  // dividend
+                    ; test rcx, rcx
+                    ; jnz  >nonzero_sdiv
+                    ; xor  rax, rax
+                    ; jmp  >sdiv_done
+                    ; nonzero_sdiv:
+                    // idiv faults on MIN / -1; negating wraps MIN to MIN instead
+                    ; cmp  rcx, -1
+                    ; jne  >safe_sdiv
+                    ; neg  rax
+                    ; jmp  >sdiv_done
+                    ; safe_sdiv:
+                    ; cqo
+                    ; idiv rcx
+                    ; sdiv_done:
+                    ; add  rbp, 8
+                    ; mov  [rbp], rax
+                );
+                pc += 1;
+            }
+            SMOD => {
+                dynasm!(ops
+                    ; mov  rcx, [rbp]      // divisor
+                    ; mov  rax, [rbp + 8]  // dividend
+                    ; xor  rdx, rdx
+                    ; test rcx, rcx
+                    ; jz   >smod_done
+                    // x % -1 is always 0, and idiv would fault on MIN % -1
+                    ; cmp  rcx, -1
+                    ; je   >smod_done
+                    ; cqo
+                    ; idiv rcx
+                    ; smod_done:
+                    ; add  rbp, 8
+                    ; mov  [rbp], rdx
+                );
+                pc += 1;
+            }
+            SLT => {
+                dynasm!(ops
+                    ; mov  rdx, [rbp]      // b
+                    ; add  rbp, 8
+                    ; cmp  [rbp], rdx      // a
+                    ; setl al
+                    ; movzx rax, al
+                    ; mov  [rbp], rax
+                );
+                pc += 1;
+            }
+            SGT => {
+                dynasm!(ops
+                    ; mov  rdx, [rbp]      // b
+                    ; add  rbp, 8
+                    ; cmp  [rbp], rdx      // a
+                    ; setg al
+                    ; movzx rax, al
+                    ; mov  [rbp], rax
+                );
+                pc += 1;
+            }
+            SIGNEXTEND => {
+                dynasm!(ops
+                    ; mov  rcx, [rbp]      // byte index
+                    ; add  rbp, 8
+                    ; cmp  rcx, 6
+                    ; ja   >signext_done
+                    // shift the sign byte to the top and arithmetic-shift back
+                    ; mov  rax, [rbp]      // value
+                    ; shl  rcx, 3
+                    ; neg  rcx
+                    ; add  rcx, 56
+                    ; shl  rax, cl
+                    ; sar  rax, cl
+                    ; mov  [rbp], rax
+                    ; signext_done:
                 );
                 pc += 1;
             }
             AND => {
                 dynasm!(ops
-                    ; pop  rax
-                    ; pop  rdx
-                    ; and  rax, rdx
-                    ; push rax
+                    ; mov  rax, [rbp]
+                    ; add  rbp, 8
+                    ; and  [rbp], rax
                 );
                 pc += 1;
             }
             OR => {
                 dynasm!(ops
-                    ; pop  rax
-                    ; pop  rdx
-                    ; or   rax, rdx
-                    ; push rax
+                    ; mov  rax, [rbp]
+                    ; add  rbp, 8
+                    ; or   [rbp], rax
                 );
                 pc += 1;
             }
             XOR => {
                 dynasm!(ops
-                    ; pop  rax
-                    ; pop  rdx
-                    ; xor  rax, rdx
-                    ; push rax
+                    ; mov  rax, [rbp]
+                    ; add  rbp, 8
+                    ; xor  [rbp], rax
                 );
                 pc += 1;
             }
-            DUP => {
+            SHL => {
                 dynasm!(ops
-                    ; mov  rax, [rsp]
-                    ; push rax
+                    ; mov  rcx, [rbp]      // shift
+                    ; mov  rax, [rbp + 8]  // value
+                    ; cmp  rcx, 63
+                    ; ja   >shl_zero
+                    ; shl  rax, cl
+                    ; jmp  >shl_done
+                    ; shl_zero:
+                    ; xor  rax, rax
+                    ; shl_done:
+                    ; add  rbp, 8
+                    ; mov  [rbp], rax
                 );
                 pc += 1;
             }
-            SWAP => {
+            SHR => {
                 dynasm!(ops
-                    ; pop  rax
-                    ; pop  rdx
-                    ; push rax
-                    ; push rdx
+                    ; mov  rcx, [rbp]      // shift
+                    ; mov  rax, [rbp + 8]  // value
+                    ; cmp  rcx, 63
+                    ; ja   >shr_zero
+                    ; shr  rax, cl
+                    ; jmp  >shr_done
+                    ; shr_zero:
+                    ; xor  rax, rax
+                    ; shr_done:
+                    ; add  rbp, 8
+                    ; mov  [rbp], rax
+                );
+                pc += 1;
+            }
+            SAR => {
+                dynasm!(ops
+                    ; mov  rcx, [rbp]      // shift
+                    ; mov  rax, [rbp + 8]  // value
+                    ; cmp  rcx, 63
+                    ; jbe  >sar_shift
+                    ; mov  rcx, 63
+                    ; sar_shift:
+                    ; sar  rax, cl
+                    ; add  rbp, 8
+                    ; mov  [rbp], rax
+                );
+                pc += 1;
+            }
+            NOT => {
+                dynasm!(ops
+                    ; not  QWORD [rbp]
+                );
+                pc += 1;
+            }
+            ISZERO => {
+                dynasm!(ops
+                    ; xor  eax, eax
+                    ; cmp  QWORD [rbp], 0
+                    ; sete al
+                    ; mov  [rbp], rax
+                );
+                pc += 1;
+            }
+            BYTE => {
+                dynasm!(ops
+                    ; mov  rcx, [rbp]      // byte index
+                    ; mov  rax, [rbp + 8]  // value
+                    ; cmp  rcx, 7
+                    ; ja   >byte_zero
+                    // shift right by (7 - i) * 8 and keep the low byte
+                    ; neg  rcx
+                    ; add  rcx, 7
+                    ; shl  rcx, 3
+                    ; shr  rax, cl
+                    ; movzx rax, al
+                    ; jmp  >byte_done
+                    ; byte_zero:
+                    ; xor  rax, rax
+                    ; byte_done:
+                    ; add  rbp, 8
+                    ; mov  [rbp], rax
+                );
+                pc += 1;
+            }
+            ADDMOD => {
+                dynasm!(ops
+                    ; mov  rax, [rbp]       // a
+                    ; mov  r8, [rbp + 8]    // b
+                    ; mov  rcx, [rbp + 16]  // modulus
+                    // x % 1 is 0 too; for a modulus >= 2 the carry of the
+                    // 65-bit sum is below it, so div cannot overflow
+                    ; cmp  rcx, 1
+                    ; jbe  >addmod_zero
+                    ; xor  rdx, rdx
+                    ; add  rax, r8
+                    ; adc  rdx, 0
+                    ; div  rcx
+                    ; mov  rax, rdx
+                    ; jmp  >addmod_done
+                    ; addmod_zero:
+                    ; xor  rax, rax
+                    ; addmod_done:
+                    ; add  rbp, 16
+                    ; mov  [rbp], rax
+                );
+                pc += 1;
+            }
+            MULMOD => {
+                dynasm!(ops
+                    ; mov  rax, [rbp]       // a
+                    ; mov  r8, [rbp + 8]    // b
+                    ; mov  rcx, [rbp + 16]  // modulus
+                    ; cmp  rcx, 1
+                    ; jbe  >mulmod_zero
+                    // reduce a first so the high half of the 128-bit product
+                    // stays below the modulus
+                    ; xor  rdx, rdx
+                    ; div  rcx
+                    ; mov  rax, rdx
+                    ; mul  r8
+                    ; div  rcx
+                    ; mov  rax, rdx
+                    ; jmp  >mulmod_done
+                    ; mulmod_zero:
+                    ; xor  rax, rax
+                    ; mulmod_done:
+                    ; add  rbp, 16
+                    ; mov  [rbp], rax
+                );
+                pc += 1;
+            }
+            EXP => {
+                dynasm!(ops
+                    ; mov  r8, [rbp]       // base
+                    ; mov  rcx, [rbp + 8]  // exponent
+                    ; mov  rax, 1
+                    ; test rcx, rcx
+                    ; jz   >exp_done
+                    // dynamic gas: EXP_BYTE_COST per byte of the exponent
+                    ; bsr  rdx, rcx
+                    ; shr  rdx, 3
+                    ; inc  rdx
+                    ; imul rdx, rdx, gas::EXP_BYTE_COST as i32
+                    ; add  [r12 + FRAME_GAS], rdx
+                    // square-and-multiply
+                    ; exp_loop:
+                    ; test rcx, 1
+                    ; jz   >exp_square
+                    ; imul rax, r8
+                    ; exp_square:
+                    ; imul r8, r8
+                    ; shr  rcx, 1
+                    ; jnz  <exp_loop
+                    ; exp_done:
+                    ; add  rbp, 8
+                    ; mov  [rbp], rax
+                );
+                pc += 1;
+            }
+            // FRAME_STACK_BASE is the bottom of the operand stack, so the
+            // ZeroFill checks below compare the address just past the
+            // deepest item with it
+            POP => {
+                if short {
+                    dynasm!(ops
+                        ; cmp  rbp, [r12 + FRAME_STACK_BASE]
+                        ; jae  >pop_done
+                        ; add  rbp, 8
+                        ; pop_done:
+                    );
+                } else {
+                    dynasm!(ops
+                        ; add  rbp, 8
+                    );
+                }
+                pc += 1;
+            }
+            // DUP and SWAP are DUP1 and SWAP1 under another name
+            DUP | DUP1..=DUP16 => {
+                let n = match code[pc] {
+                    DUP => 1,
+                    op => (op - DUP1 + 1) as i32,
+                };
+                if short {
+                    dynasm!(ops
+                        ; lea  rax, [rbp + n * 8]
+                        ; cmp  rax, [r12 + FRAME_STACK_BASE]
+                        ; ja   >dup_under
+                        ; mov  rax, [rbp + (n - 1) * 8]
+                        ; jmp  >dup_done
+                        ; dup_under:
+                        ; xor  rax, rax
+                        ; dup_done:
+                    );
+                } else {
+                    dynasm!(ops
+                        ; mov  rax, [rbp + (n - 1) * 8]
+                    );
+                }
+                dynasm!(ops
+                    ; sub  rbp, 8
+                    ; mov  [rbp], rax
+                );
+                pc += 1;
+            }
+            SWAP | SWAP1..=SWAP16 => {
+                let n = match code[pc] {
+                    SWAP => 1,
+                    op => (op - SWAP1 + 1) as i32,
+                };
+                if short {
+                    dynasm!(ops
+                        ; lea  rax, [rbp + (n + 1) * 8]
+                        ; cmp  rax, [r12 + FRAME_STACK_BASE]
+                        ; ja   >swap_done
+                    );
+                }
+                dynasm!(ops
+                    ; mov  rax, [rbp]
+                    ; mov  rdx, [rbp + n * 8]
+                    ; mov  [rbp], rdx
+                    ; mov  [rbp + n * 8], rax
+                    ; swap_done:
+                );
+                pc += 1;
+            }
+            CALLDATALOAD => {
+                dynasm!(ops
+                    ; mov  rax, [rbp]  // offset
+                    ; mov  rdx, r15
+                    ; sub  rdx, 8
+                    ; jb   >slow_cdl
+                    ; cmp  rax, rdx
+                    ; ja   >slow_cdl
+                    ; mov  rax, [r14 + rax]
+                    ; bswap rax
+                    ; jmp  >cdl_done
+                    ; slow_cdl:
+                    ; mov  rdi, r14
+                    ; mov  rsi, r15
+                    ; mov  rdx, rax
+                );
+                emit_call(&mut ops, runtime::jit_load_word as *const ());
+                dynasm!(ops
+                    ; cdl_done:
+                    ; mov  [rbp], rax
+                );
+                pc += 1;
+            }
+            CALLDATASIZE => {
+                dynasm!(ops
+                    ; sub  rbp, 8
+                    ; mov  [rbp], r15
+                );
+                pc += 1;
+            }
+            DATALOAD => {
+                dynasm!(ops
+                    ; mov  rdx, [rbp]  // offset
+                    ; xor  eax, eax
+                    ; cmp  rdx, data_len
+                    ; ja   >dl_done
+                    ; lea  rax, [->data]
+                    ; mov  rax, [rax + rdx]
+                    ; bswap rax
+                    ; dl_done:
+                    ; mov  [rbp], rax
+                );
+                pc += 1;
+            }
+            DATALOADN => {
+                let offset = u16::from_be_bytes([code[pc + 1], code[pc + 2]]) as usize;
+                if offset <= data.len() {
+                    dynasm!(ops
+                        ; mov  rax, [->data + offset as isize]
+                        ; bswap rax
+                        ; sub  rbp, 8
+                        ; mov  [rbp], rax
+                    );
+                } else {
+                    dynasm!(ops
+                        ; sub  rbp, 8
+                        ; mov  QWORD [rbp], 0
+                    );
+                }
+                pc += 3;
+            }
+            DATASIZE => {
+                dynasm!(ops
+                    ; sub  rbp, 8
+                    ; mov  QWORD [rbp], data_len
+                );
+                pc += 1;
+            }
+            DATACOPY => {
+                dynasm!(ops
+                    ; mov  rcx, [rbp]       // dest
+                    ; mov  r8, [rbp + 8]    // offset
+                    ; mov  r9, [rbp + 16]   // size
+                    ; add  rbp, 24
+                    ; mov  rdi, r13
+                    ; lea  rsi, [->data]
+                    ; mov  edx, data_len
+                );
+                emit_call(&mut ops, runtime::jit_copy_to_memory as *const ());
+                pc += 1;
+            }
+            CALLDATACOPY => {
+                dynasm!(ops
+                    ; mov  rcx, [rbp]       // dest
+                    ; mov  r8, [rbp + 8]    // offset
+                    ; mov  r9, [rbp + 16]   // size
+                    ; add  rbp, 24
+                    ; mov  rdi, r13
+                    ; mov  rsi, r14
+                    ; mov  rdx, r15
+                );
+                emit_call(&mut ops, runtime::jit_copy_to_memory as *const ());
+                pc += 1;
+            }
+            MLOAD => {
+                dynasm!(ops
+                    ; mov  rax, [rbp]  // offset
+                    ; cmp  rax, MEMORY_FAST_LIMIT
+                    ; ja   >slow_mload
+                    ; mov  rax, [r13 + rax]
+                    ; bswap rax
+                    ; jmp  >mload_done
+                    ; slow_mload:
+                    ; mov  rdi, r13
+                    ; mov  rsi, MEMORY_SIZE as i32
+                    ; mov  rdx, rax
+                );
+                emit_call(&mut ops, runtime::jit_load_word as *const ());
+                dynasm!(ops
+                    ; mload_done:
+                    ; mov  [rbp], rax
+                );
+                pc += 1;
+            }
+            MSTORE => {
+                dynasm!(ops
+                    ; mov  rsi, [rbp]      // offset
+                    ; mov  rdx, [rbp + 8]  // value
+                    ; add  rbp, 16
+                    ; cmp  rsi, MEMORY_FAST_LIMIT
+                    ; ja   >slow_mstore
+                    ; bswap rdx
+                    ; mov  [r13 + rsi], rdx
+                    ; jmp  >mstore_done
+                    ; slow_mstore:
+                    ; mov  rdi, r13
+                );
+                emit_call(&mut ops, runtime::jit_store_word as *const ());
+                dynasm!(ops
+                    ; mstore_done:
+                );
+                pc += 1;
+            }
+            BEGINSUB => {
+                // falling into a subroutine is an error; CALLSUB enters
+                // through the label right after the check
+                emit_charge(&mut ops, &mut block_gas);
+                dynasm!(ops
+                    ; mov eax, Status::WalkIntoSubroutine as i32
+                    ; jmp ->exit
+                    ; =>labels[pc].unwrap()
+                );
+                block_gas = gas::static_cost(BEGINSUB);
+                pc += 1;
+            }
+            CALLSUB => {
+                let target = u16::from_be_bytes([code[pc + 1], code[pc + 2]]) as usize;
+                emit_charge(&mut ops, &mut block_gas);
+                match labels.get(target).copied().flatten() {
+                    Some(label) => dynasm!(ops
+                        ; mov  rax, [r12 + FRAME_RS_TOP]
+                        ; lea  rdx, [r12 + FRAME_SIZE]
+                        ; cmp  rax, rdx
+                        ; jae  ->return_stack_overflow
+                        ; lea  rdx, [>return_site]
+                        ; mov  [rax], rdx
+                        ; add  rax, 8
+                        ; mov  [r12 + FRAME_RS_TOP], rax
+                        ; jmp  =>label
+                        ; return_site:
+                    ),
+                    None => dynasm!(ops
+                        ; mov eax, Status::InvalidJump as i32
+                        ; jmp ->exit
+                    ),
+                }
+                pc += 3;
+            }
+            RETSUB => {
+                emit_charge(&mut ops, &mut block_gas);
+                dynasm!(ops
+                    ; mov  rax, [r12 + FRAME_RS_TOP]
+                    ; lea  rdx, [r12 + FRAME_RS_BASE]
+                    ; cmp  rax, rdx
+                    ; jbe  ->return_stack_underflow
+                    ; sub  rax, 8
+                    ; mov  [r12 + FRAME_RS_TOP], rax
+                    ; jmp  QWORD [rax]
+                );
+                pc += 1;
+            }
+            CALL => {
+                emit_charge(&mut ops, &mut block_gas);
+                // the helper reads its six operands straight off the stack
+                dynasm!(ops
+                    ; mov  rdi, [r12 + FRAME_HOST]
+                    ; mov  rsi, r13
+                    ; mov  rdx, rbp
+                    ; mov  rcx, [r12 + FRAME_ENV]
+                );
+                emit_call(&mut ops, host::jit_call as *const ());
+                dynasm!(ops
+                    ; cmp  rax, 1
+                    ; ja   ->trap  // the callee trapped
+                    ; add  rbp, 40
+                    ; mov  [rbp], rax
+                    ; add  [r12 + FRAME_GAS], rdx
+                    ; jnc  >call_charged
+                    ; mov  QWORD [r12 + FRAME_GAS], -1
+                    ; call_charged:
+                );
+                emit_check(&mut ops);
+                pc += 1;
+            }
+            LOG0..=LOG4 => {
+                let topics = (code[pc] - LOG0) as i32;
+                emit_charge(&mut ops, &mut block_gas);
+                dynasm!(ops
+                    ; mov  rdi, [r12 + FRAME_HOST]
+                    ; mov  rsi, r13
+                    ; mov  rdx, rbp
+                    ; mov  ecx, topics
+                );
+                emit_call(&mut ops, host::jit_log as *const ());
+                dynasm!(ops
+                    ; add  rbp, (2 + topics) * 8
+                    ; add  [r12 + FRAME_GAS], rax
+                    ; jnc  >log_charged
+                    ; mov  QWORD [r12 + FRAME_GAS], -1
+                    ; log_charged:
+                );
+                emit_check(&mut ops);
+                pc += 1;
+            }
+            KECCAK256 => {
+                dynasm!(ops
+                    ; mov  rdi, r13
+                    ; mov  rsi, [rbp]      // offset
+                    ; mov  rdx, [rbp + 8]  // size
+                    ; add  rbp, 8
+                );
+                emit_call(&mut ops, runtime::jit_hash_memory as *const ());
+                dynasm!(ops
+                    ; mov  [rbp], rax
+                    ; add  [r12 + FRAME_GAS], rdx
+                    ; jnc  >hash_charged
+                    ; mov  QWORD [r12 + FRAME_GAS], -1
+                    ; hash_charged:
+                );
+                pc += 1;
+            }
+            CALLER | ADDRESS | CALLVALUE | NUMBER | TIMESTAMP | CHAINID => {
+                let field = env_offset(code[pc]);
+                dynasm!(ops
+                    ; mov  rax, [r12 + FRAME_ENV]
+                    ; mov  rax, [rax + field]
+                    ; sub  rbp, 8
+                    ; mov  [rbp], rax
+                );
+                pc += 1;
+            }
+            GAS => {
+                // the counter must include everything charged up to here
+                emit_charge(&mut ops, &mut block_gas);
+                dynasm!(ops
+                    ; mov  rax, [r12 + FRAME_GAS_LIMIT]
+                    ; sub  rax, [r12 + FRAME_GAS]
+                    ; sub  rbp, 8
+                    ; mov  [rbp], rax
+                );
+                pc += 1;
+            }
+            PC => {
+                dynasm!(ops
+                    ; mov  rax, QWORD pc as i64
+                    ; sub  rbp, 8
+                    ; mov  [rbp], rax
+                );
+                pc += 1;
+            }
+            RETURN => {
+                emit_charge(&mut ops, &mut block_gas);
+                dynasm!(ops
+                    ; mov  rdi, [r12 + FRAME_HOST]
+                    ; mov  rsi, r13
+                    ; mov  rdx, [rbp]      // offset
+                    ; mov  rcx, [rbp + 8]  // size
+                    ; add  rbp, 16
+                );
+                emit_call(&mut ops, host::jit_return as *const ());
+                dynasm!(ops
+                    ; xor eax, eax
+                    ; jmp ->exit
                 );
                 pc += 1;
             }
             STOP => {
+                emit_charge(&mut ops, &mut block_gas);
                 dynasm!(ops
-                    ; mov rsp, r12
-                    ; pop r12
-                    ; pop rbx
-                    ; ret
+                    ; xor eax, eax
+                    ; jmp ->exit
                 );
                 pc += 1;
             }
             _ => panic!("bad opcode: {}", code[pc]),
         }
+        if padded {
+            emit_unpad(&mut ops, pushes);
+        }
+    }
+
+    let body_end = ops.offset().0;
+    emit_charge(&mut ops, &mut block_gas);
+    dynasm!(ops
+        ; xor eax, eax
+        ; jmp ->exit
+        ; ->return_stack_overflow:
+        ; mov eax, Status::ReturnStackOverflow as i32
+        ; jmp ->exit
+        ; ->return_stack_underflow:
+        ; mov eax, Status::ReturnStackUnderflow as i32
+        ; jmp ->exit
+        // RAX holds the static gas of the faulting block not yet charged;
+        // running out of gas takes precedence, as in the interpreter
+        ; ->stack_underflow:
+        ; mov ecx, Status::StackUnderflow as i32
+        ; jmp >stack_fault
+        ; ->stack_overflow:
+        ; mov ecx, Status::StackOverflow as i32
+        ; stack_fault:
+        ; add rax, [r12 + FRAME_GAS]
+        ; jc  ->out_of_gas
+        ; cmp rax, [r12 + FRAME_GAS_LIMIT]
+        ; ja  ->out_of_gas
+        ; mov rax, [r12 + FRAME_GAS_LIMIT]
+        ; mov [r12 + FRAME_GAS], rax
+        ; mov eax, ecx
+        ; jmp ->exit
+        ; ->storage_too_small:
+        ; mov eax, Status::StorageTooSmall as i32
+        ; jmp ->exit
+        // resumed here by the fault handler, or on a trapped callee
+        ; ->trap:
+        ; mov rax, [r12 + FRAME_GAS_LIMIT]
+        ; mov [r12 + FRAME_GAS], rax
+        ; mov eax, Status::Trapped as i32
+        ; jmp ->exit
+        ; ->out_of_gas:
+        ; mov rax, [r12 + FRAME_GAS_LIMIT]
+        ; mov [r12 + FRAME_GAS], rax
+        ; mov eax, Status::OutOfGas as i32
+        // epilogue: report the final depth, status is in RAX and gas used
+        // goes to RDX
+        ; ->exit:
+        ; mov rcx, [r12 + FRAME_STACK]
+        ; mov rdx, [r12 + FRAME_STACK_BASE]
+        ; sub rdx, rbp
+        ; jnc >len_set
+        ; xor edx, edx  // RETURN short of operands under ZeroFill
+        ; len_set:
+        ; shr rdx, 3
+        ; mov [rcx + STACK_LEN], rdx
+        ; mov rdx, [r12 + FRAME_GAS]
+        ; lea rsp, [r12 + FRAME_SIZE]
+        ; pop r15
+        ; pop r14
+        ; pop r13
+        ; pop r12
+        ; pop rbp
+        ; pop rbx
+        ; ret
+        ; ->data:
+    );
+    ops.extend(data);
+    ops.extend([0u8; 8]);
+
+    let trap = ops.labels().resolve_global("trap").unwrap().0;
+    let map = CodeMap {
+        pcs,
+        body: body_start..body_end,
+        trap,
+    };
+    (ops.finalize().unwrap(), map)
+}
+
+/// Offset within [`Environment`] of the field read by an environment opcode.
+fn env_offset(op: u8) -> i32 {
+    let offset = match op {
+        CALLER => std::mem::offset_of!(Environment, caller),
+        ADDRESS => std::mem::offset_of!(Environment, address),
+        CALLVALUE => std::mem::offset_of!(Environment, callvalue),
+        NUMBER => std::mem::offset_of!(Environment, number),
+        TIMESTAMP => std::mem::offset_of!(Environment, timestamp),
+        CHAINID => std::mem::offset_of!(Environment, chain_id),
+        _ => unreachable!(),
+    };
+    offset as i32
+}
+
+/// Exits with `OutOfGas` if the gas counter has passed the limit. The
+/// interpreter runs nothing after an instruction whose dynamic gas does
+/// this, so checking straight away is the same as at its next instruction.
+fn emit_check(ops: &mut Assembler) {
+    dynasm!(ops
+        ; .arch x64
+        ; mov rax, [r12 + FRAME_GAS]
+        ; cmp rax, [r12 + FRAME_GAS_LIMIT]
+        ; ja  ->out_of_gas
+    );
+}
+
+/// Adds the static gas of the block compiled so far to the gas counter and
+/// exits with `OutOfGas` once it passes the limit. Besides at the end of
+/// every block, it runs before each instruction whose effects outlast a
+/// failed run (SSTORE, TSTORE, CALL, LOGn and RETURN), which the interpreter
+/// never reaches once the gas is gone. The dynamic gas of the other
+/// instructions is charged while their block's static gas is still pending,
+/// so this check covers both; CALL and LOGn check theirs with [`emit_check`].
+fn emit_charge(ops: &mut Assembler, block_gas: &mut u64) {
+    if *block_gas != 0 {
+        dynasm!(ops
+            ; .arch x64
+            ; mov rax, QWORD *block_gas as i64
+            ; add rax, [r12 + FRAME_GAS]
+            ; jc  ->out_of_gas
+            ; mov [r12 + FRAME_GAS], rax
+            ; cmp rax, [r12 + FRAME_GAS_LIMIT]
+            ; ja  ->out_of_gas
+        );
+        *block_gas = 0;
     }
+}
+
+/// Follows an instruction that may have run short of operands under
+/// `UnderflowPolicy::ZeroFill`. If it did, RBP is past the bottom of the
+/// stack, with its result (instructions that pop push at most one) in the
+/// red zone: the result is moved to the bottom and the red zone re-zeroed.
+fn emit_unpad(ops: &mut Assembler, pushes: usize) {
+    if pushes == 0 {
+        dynasm!(ops
+            ; .arch x64
+            ; cmp  rbp, [r12 + FRAME_STACK_BASE]
+            ; jbe  >unpadded
+            ; mov  rbp, [r12 + FRAME_STACK_BASE]
+            ; unpadded:
+        );
+    } else {
+        dynasm!(ops
+            ; .arch x64
+            ; cmp  rbp, [r12 + FRAME_STACK_BASE]
+            ; jb   >unpadded
+            ; mov  rax, [rbp]
+            ; mov  QWORD [rbp], 0
+            ; mov  rbp, [r12 + FRAME_STACK_BASE]
+            ; sub  rbp, 8
+            ; mov  [rbp], rax
+            ; unpadded:
+        );
+    }
+}
 
-    ops.finalize().unwrap()
+/// Calls a runtime helper with the arguments already in place. RSP is kept
+/// aligned below the frame, so no realignment is needed. The result is left
+/// in RAX.
+fn emit_call(ops: &mut Assembler, helper: *const ()) {
+    dynasm!(ops
+        ; .arch x64
+        ; mov  rax, QWORD helper as i64
+        ; call rax
+    );
 }
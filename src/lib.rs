@@ -0,0 +1,52 @@
+//! A bytecode interpreter and an x86-64 JIT compiler for the same
+//! EVM-flavoured instruction set, with 64-bit words.
+//!
+//! [`VM`] interprets bytecode, or a validated [`Container`], on any target.
+//! With the `jit` feature (on by default, x86-64 Linux only),
+//! `CompiledProgram` compiles it to machine code and runs it against a
+//! `Context`; both engines share the gas schedule, the limits in
+//! [`Config`] and every edge case, so they halt with the same [`Status`].
+//! Interpreter-only builds use `default-features = false`. The `ffi`
+//! feature, also on by default, exposes both engines to C through `ffi`,
+//! and `cli` only builds the `poc-jit` command line.
+//!
+//! [`evm::decode`] translates standard EVM bytecode into this instruction
+//! set, whose opcodes are in [`instructions::opcodes`], and [`asm`] reads and
+//! writes it as text.
+
+#[cfg(all(feature = "jit", not(all(target_arch = "x86_64", target_os = "linux"))))]
+compile_error!("the `jit` feature needs x86-64 Linux; build with `default-features = false`");
+
+pub mod asm;
+pub mod config;
+pub mod container;
+pub mod env;
+pub mod evm;
+#[cfg(feature = "ffi")]
+pub mod ffi;
+pub mod gas;
+#[cfg(feature = "jit")]
+pub mod host;
+pub mod instructions;
+#[cfg(feature = "jit")]
+pub mod jit;
+pub mod keccak;
+#[cfg(feature = "jit")]
+pub mod program;
+mod runtime;
+pub mod status;
+#[cfg(feature = "jit")]
+pub mod trap;
+pub mod vm;
+
+pub use config::{Config, UnderflowPolicy};
+pub use container::{Container, ContainerError};
+pub use env::Environment;
+pub use evm::DecodeError;
+#[cfg(feature = "jit")]
+pub use program::{CompiledProgram, Context, Outcome};
+pub use runtime::{CALL_DEPTH_LIMIT, Log, MEMORY_SIZE, RETURN_STACK_LIMIT, STORAGE_SLOTS};
+pub use status::Status;
+#[cfg(feature = "jit")]
+pub use trap::Trap;
+pub use vm::VM;
@@ -0,0 +1,221 @@
+//! Safe interface to compiled code.
+//!
+//! [`CompiledProgram`] owns the machine code built by the JIT and is the only
+//! way to run it outside this crate's internals: it is run against a
+//! [`Context`], which owns storage and memory of the sizes compiled code
+//! expects and a [`Host`] built with the same config, so the raw entry point
+//! is never handed mismatched arguments.
+
+use crate::config::Config;
+use crate::container::{Container, ContainerError, validate_bytecode};
+use crate::env::Environment;
+use crate::host::Host;
+use crate::jit::{CodeMap, JitFn, make_jit, make_jit_container};
+use crate::keccak::keccak256;
+use crate::runtime::{MEMORY_SIZE, STORAGE_SLOTS};
+use crate::status::Status;
+use crate::trap::Trap;
+use dynasmrt::{AssemblyOffset, ExecutableBuffer};
+use std::time::{Duration, Instant};
+
+/// How a run of a [`CompiledProgram`] ended.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Outcome {
+    pub status: Status,
+    pub gas_used: u64,
+}
+
+/// State a compiled program runs against: the host with the programs it can
+/// CALL, the top-level program's storage and memory, and the inputs of the
+/// next run. Storage and memory persist across runs; return data, logs and
+/// the final operand stack of the last run are kept by the host.
+pub struct Context {
+    host: Host,
+    storage: Vec<u64>,
+    memory: Vec<u8>,
+    pub calldata: Vec<u8>,
+    pub env: Environment,
+    pub gas_limit: u64,
+}
+
+impl Context {
+    /// A context with zeroed storage and memory, no call data, a default
+    /// environment and no gas.
+    pub fn new(config: Config) -> Self {
+        Self {
+            host: Host::new(config),
+            storage: vec![0; STORAGE_SLOTS],
+            memory: vec![0; MEMORY_SIZE],
+            calldata: Vec::new(),
+            env: Environment::default(),
+            gas_limit: 0,
+        }
+    }
+
+    pub fn config(&self) -> &Config {
+        self.host.config()
+    }
+
+    pub fn host(&self) -> &Host {
+        &self.host
+    }
+
+    /// The host, e.g. to deploy programs the top-level one can CALL.
+    pub fn host_mut(&mut self) -> &mut Host {
+        &mut self.host
+    }
+
+    /// Storage slots of the top-level program, indexed by key.
+    pub fn storage(&self) -> &[u64] {
+        &self.storage
+    }
+
+    pub fn storage_mut(&mut self) -> &mut [u64] {
+        &mut self.storage
+    }
+
+    pub fn memory(&self) -> &[u8] {
+        &self.memory
+    }
+}
+
+/// Machine code compiled from a program, with what is known about it.
+pub struct CompiledProgram {
+    buf: ExecutableBuffer,
+    map: CodeMap,
+    config: Config,
+    hash: [u8; 32],
+    compile_time: Duration,
+}
+
+impl CompiledProgram {
+    /// Compiles `code`, once it passes [`validate_bytecode`].
+    pub fn new(code: &[u8], config: &Config) -> Result<Self, ContainerError> {
+        validate_bytecode(code)?;
+        let start = Instant::now();
+        let (buf, map) = make_jit(code, config);
+        let compile_time = start.elapsed();
+        Ok(Self::from_parts(
+            buf,
+            map,
+            config,
+            keccak256(code),
+            compile_time,
+        ))
+    }
+
+    /// Compiles a validated container.
+    pub fn from_container(container: &Container, config: &Config) -> Self {
+        let start = Instant::now();
+        let (buf, map) = make_jit_container(container, config);
+        let compile_time = start.elapsed();
+        let code = container.code();
+        let sections: Vec<&[u8]> = container
+            .sections()
+            .iter()
+            .map(|s| &code[s.clone()])
+            .collect();
+        let bytes = Container::encode(&sections, container.data(), container.max_stack_height());
+        Self::from_parts(buf, map, config, keccak256(&bytes), compile_time)
+    }
+
+    fn from_parts(
+        buf: ExecutableBuffer,
+        map: CodeMap,
+        config: &Config,
+        hash: [u8; 32],
+        compile_time: Duration,
+    ) -> Self {
+        Self {
+            buf,
+            map,
+            config: *config,
+            hash,
+            compile_time,
+        }
+    }
+
+    /// Runs the program with the inputs in `ctx`, against its storage,
+    /// memory and host. Panics if `ctx` was created with a different config.
+    pub fn run(&self, ctx: &mut Context) -> Outcome {
+        self.check(ctx);
+        let result = unsafe {
+            ctx.host.run(
+                self.entry(),
+                &mut ctx.storage,
+                &mut ctx.memory,
+                &ctx.calldata,
+                ctx.gas_limit,
+                &ctx.env,
+            )
+        };
+        Outcome {
+            status: result.status(),
+            gas_used: result.gas_used,
+        }
+    }
+
+    /// Like [`CompiledProgram::run`], but a fault in the compiled code, or in
+    /// a program it calls, is returned as a [`Trap`] instead of killing the
+    /// process. See [`Host::run_guarded`].
+    pub fn run_guarded(&self, ctx: &mut Context) -> Result<Outcome, Trap> {
+        self.check(ctx);
+        let result = unsafe {
+            ctx.host.run_guarded(
+                &self.buf,
+                &self.map,
+                &mut ctx.storage,
+                &mut ctx.memory,
+                &ctx.calldata,
+                ctx.gas_limit,
+                &ctx.env,
+            )
+        }?;
+        Ok(Outcome {
+            status: result.status(),
+            gas_used: result.gas_used,
+        })
+    }
+
+    /// Config the program was compiled with; it only runs in a [`Context`]
+    /// created with the same one.
+    pub fn config(&self) -> &Config {
+        &self.config
+    }
+
+    /// Where the machine code of each instruction lies.
+    pub fn map(&self) -> &CodeMap {
+        &self.map
+    }
+
+    /// Keccak-256 of the bytecode, or of the encoded container.
+    pub fn hash(&self) -> [u8; 32] {
+        self.hash
+    }
+
+    /// The machine code, followed by the constant pool of a container.
+    pub fn code(&self) -> &[u8] {
+        &self.buf
+    }
+
+    /// Size of [`CompiledProgram::code`] in bytes.
+    pub fn code_size(&self) -> usize {
+        self.buf.len()
+    }
+
+    pub fn compile_time(&self) -> Duration {
+        self.compile_time
+    }
+
+    fn entry(&self) -> JitFn {
+        unsafe { std::mem::transmute(self.buf.ptr(AssemblyOffset(0))) }
+    }
+
+    fn check(&self, ctx: &Context) {
+        assert_eq!(
+            *ctx.config(),
+            self.config,
+            "context config differs from the program's"
+        );
+    }
+}
@@ -0,0 +1,137 @@
+//! Semantics shared by the interpreter and JIT-compiled code.
+//!
+//! The interpreter calls the safe functions directly; JIT code falls back to
+//! the `jit_*` wrappers (System V ABI) whenever its inline fast path does not
+//! apply, so both engines agree on every edge case.
+
+use crate::keccak::keccak256;
+
+/// Size in bytes of the linear memory available to a program.
+pub const MEMORY_SIZE: usize = 1024;
+
+/// Storage and transient storage slots of a program, matching the 256 keys
+/// SLOAD/SSTORE and TLOAD/TSTORE can address.
+pub const STORAGE_SLOTS: usize = 256;
+
+/// Maximum number of nested CALLSUBs.
+pub const RETURN_STACK_LIMIT: usize = 1024;
+
+/// Maximum nesting of cross-program CALLs. JIT frames live on the native
+/// stack, so this is kept well below the EVM's 1024.
+pub const CALL_DEPTH_LIMIT: usize = 64;
+
+/// Record emitted by LOGn.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Log {
+    pub topics: Vec<u64>,
+    pub data: Vec<u8>,
+}
+
+/// Fills `dst` from `src[offset..]`, zero-padding past the end of `src`.
+pub fn copy_padded(dst: &mut [u8], src: &[u8], offset: u64) {
+    let start = usize::try_from(offset).unwrap_or(usize::MAX).min(src.len());
+    let n = (src.len() - start).min(dst.len());
+    dst[..n].copy_from_slice(&src[start..start + n]);
+    dst[n..].fill(0);
+}
+
+/// Reads the big-endian word at `offset`, zero-padding past the end of `src`.
+pub fn load_word(src: &[u8], offset: u64) -> u64 {
+    let mut word = [0u8; 8];
+    copy_padded(&mut word, src, offset);
+    u64::from_be_bytes(word)
+}
+
+/// Writes `val` big-endian at `offset`; bytes past the end of `mem` are dropped.
+pub fn store_word(mem: &mut [u8], offset: u64, val: u64) {
+    let dst = clip(mem, offset, 8);
+    let n = dst.len();
+    dst.copy_from_slice(&val.to_be_bytes()[..n]);
+}
+
+/// Copies `size` bytes of `src` starting at `offset` into `mem` at `dest`.
+/// Source bytes past the end read as zero, destination bytes past the end of
+/// `mem` are dropped.
+pub fn copy_to_memory(mem: &mut [u8], dest: u64, src: &[u8], offset: u64, size: u64) {
+    copy_padded(clip(mem, dest, size), src, offset);
+}
+
+/// Hashes `mem[offset..offset + size]`, clipped to `mem`, and returns the low
+/// 64 bits of the Keccak-256 digest.
+pub fn hash_memory(mem: &[u8], offset: u64, size: u64) -> u64 {
+    let digest = keccak256(memory_slice(mem, offset, size));
+    u64::from_be_bytes(digest[24..].try_into().unwrap())
+}
+
+/// Copies as much of `data` as fits in `size` bytes into `mem` at `dest`,
+/// dropping bytes past the end of `mem`. Unlike call data, return data is
+/// not zero-padded.
+pub fn copy_return_data(mem: &mut [u8], dest: u64, size: u64, data: &[u8]) {
+    let dst = clip(mem, dest, size.min(data.len() as u64));
+    let n = dst.len();
+    dst.copy_from_slice(&data[..n]);
+}
+
+/// Returns the part of `mem[offset..offset + size]` that lies inside `mem`.
+pub fn memory_slice(mem: &[u8], offset: u64, size: u64) -> &[u8] {
+    &mem[clip_range(mem.len(), offset, size)]
+}
+
+fn clip(mem: &mut [u8], offset: u64, size: u64) -> &mut [u8] {
+    let range = clip_range(mem.len(), offset, size);
+    &mut mem[range]
+}
+
+fn clip_range(len: usize, offset: u64, size: u64) -> std::ops::Range<usize> {
+    let start = usize::try_from(offset).unwrap_or(usize::MAX).min(len);
+    let end = usize::try_from(offset.saturating_add(size))
+        .unwrap_or(usize::MAX)
+        .min(len);
+    start..end
+}
+
+// The wrappers below trust JIT code to pass the buffers it was entered with:
+// `mem` always spans `MEMORY_SIZE` bytes and `src` spans `len` bytes.
+
+#[cfg(feature = "jit")]
+pub(crate) unsafe extern "C" fn jit_load_word(src: *const u8, len: usize, offset: u64) -> u64 {
+    let src = unsafe { std::slice::from_raw_parts(src, len) };
+    load_word(src, offset)
+}
+
+#[cfg(feature = "jit")]
+pub(crate) unsafe extern "C" fn jit_store_word(mem: *mut u8, offset: u64, val: u64) {
+    let mem = unsafe { std::slice::from_raw_parts_mut(mem, MEMORY_SIZE) };
+    store_word(mem, offset, val);
+}
+
+#[cfg(feature = "jit")]
+pub(crate) unsafe extern "C" fn jit_copy_to_memory(
+    mem: *mut u8,
+    src: *const u8,
+    len: usize,
+    dest: u64,
+    offset: u64,
+    size: u64,
+) {
+    let mem = unsafe { std::slice::from_raw_parts_mut(mem, MEMORY_SIZE) };
+    let src = unsafe { std::slice::from_raw_parts(src, len) };
+    copy_to_memory(mem, dest, src, offset, size);
+}
+
+#[cfg(feature = "jit")]
+/// Result of a helper that also incurs dynamic gas, returned in RAX:RDX.
+#[repr(C)]
+pub(crate) struct Charged {
+    value: u64,
+    gas: u64,
+}
+
+#[cfg(feature = "jit")]
+pub(crate) unsafe extern "C" fn jit_hash_memory(mem: *const u8, offset: u64, size: u64) -> Charged {
+    let mem = unsafe { std::slice::from_raw_parts(mem, MEMORY_SIZE) };
+    Charged {
+        value: hash_memory(mem, offset, size),
+        gas: crate::gas::keccak_cost(size),
+    }
+}
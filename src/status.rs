@@ -0,0 +1,49 @@
+/// Why a run halted. JIT code returns the discriminant as a raw `u64`.
+#[repr(u64)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Status {
+    /// STOP, RETURN or the end of the code was reached.
+    Stopped = 0,
+    /// CALLSUB nested deeper than `RETURN_STACK_LIMIT`.
+    ReturnStackOverflow = 1,
+    /// RETSUB with an empty return stack.
+    ReturnStackUnderflow = 2,
+    /// CALLSUB to a target that is not a BEGINSUB.
+    InvalidJump = 3,
+    /// BEGINSUB reached by falling through instead of through CALLSUB.
+    WalkIntoSubroutine = 4,
+    /// The gas limit was exceeded.
+    OutOfGas = 5,
+    /// An instruction would have grown the operand stack past the configured
+    /// limit. Consumes all gas, like `OutOfGas`.
+    StackOverflow = 6,
+    /// An instruction needed more operands than the stack holds, under
+    /// `UnderflowPolicy::Trap`. Consumes all gas, like `OutOfGas`.
+    StackUnderflow = 7,
+    /// JIT code run under `Host::run_guarded` faulted, in this frame or in
+    /// a program it called. Consumes all gas. The interpreter never halts
+    /// with this.
+    Trapped = 8,
+    /// JIT code was handed fewer storage slots than the largest key it
+    /// uses. Nothing runs and no gas is consumed. The interpreter never
+    /// halts with this.
+    StorageTooSmall = 9,
+}
+
+impl Status {
+    pub fn from_raw(raw: u64) -> Status {
+        match raw {
+            0 => Status::Stopped,
+            1 => Status::ReturnStackOverflow,
+            2 => Status::ReturnStackUnderflow,
+            3 => Status::InvalidJump,
+            4 => Status::WalkIntoSubroutine,
+            5 => Status::OutOfGas,
+            6 => Status::StackOverflow,
+            7 => Status::StackUnderflow,
+            8 => Status::Trapped,
+            9 => Status::StorageTooSmall,
+            _ => panic!("invalid status: {}", raw),
+        }
+    }
+}
@@ -0,0 +1,177 @@
+//! Recovery from hardware faults in JIT code.
+//!
+//! Compiled code checks every access it makes, so a fault means a compiler
+//! bug or bad buffers handed to the entry point, and by default it takes the
+//! whole process down. `guard` instead runs a closure with SIGSEGV, SIGBUS
+//! and SIGFPE handlers that recognise faults inside known compiled code:
+//! the faulting frame is resumed at its trap stub, which halts it with
+//! `Status::Trapped` through the normal epilogue, every JIT frame it was
+//! called from halts in turn, and the fault is reported as a [`Trap`].
+//! Faults anywhere else go to the handlers that were installed before.
+
+use crate::jit::CodeMap;
+use dynasmrt::{AssemblyOffset, ExecutableBuffer};
+use std::cell::Cell;
+use std::fmt;
+use std::sync::{Once, OnceLock};
+
+const SIGNALS: [libc::c_int; 3] = [libc::SIGSEGV, libc::SIGBUS, libc::SIGFPE];
+
+/// A fault in JIT code.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Trap {
+    /// SIGSEGV, SIGBUS or SIGFPE.
+    pub signal: i32,
+    /// Program the fault happened in: `None` for the top-level program,
+    /// otherwise the id of a deployed program.
+    pub program: Option<u64>,
+    /// Bytecode pc of the faulting instruction.
+    pub pc: usize,
+}
+
+impl fmt::Display for Trap {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let signal = match self.signal {
+            libc::SIGSEGV => "SIGSEGV",
+            libc::SIGBUS => "SIGBUS",
+            libc::SIGFPE => "SIGFPE",
+            _ => "signal",
+        };
+        match self.program {
+            Some(id) => write!(f, "{signal} at pc {} of program {id}", self.pc),
+            None => write!(f, "{signal} at pc {}", self.pc),
+        }
+    }
+}
+
+impl std::error::Error for Trap {}
+
+/// Compiled code the handlers may recover from.
+pub(crate) struct Region<'a> {
+    pub buf: &'a ExecutableBuffer,
+    pub map: &'a CodeMap,
+    pub program: Option<u64>,
+}
+
+impl Region<'_> {
+    fn start(&self) -> usize {
+        self.buf.ptr(AssemblyOffset(0)) as usize
+    }
+
+    pub fn contains(&self, address: usize) -> bool {
+        (self.start()..self.start() + self.buf.size()).contains(&address)
+    }
+}
+
+/// State of the innermost `guard` on this thread, read by the handler.
+struct Active<'a> {
+    locate: &'a dyn Fn(usize) -> Option<Region<'a>>,
+    trap: Cell<Option<Trap>>,
+}
+
+thread_local! {
+    static ACTIVE: Cell<*const Active<'static>> = const { Cell::new(std::ptr::null()) };
+}
+
+/// Handlers that were installed before ours, in the order of `SIGNALS`.
+static PREVIOUS: OnceLock<[libc::sigaction; 3]> = OnceLock::new();
+
+/// Runs `f`, recovering from faults inside the compiled code `locate` knows
+/// of: given an address, it returns the region containing it. Returns what
+/// `f` returned, and the first fault if there was one. `locate` runs inside
+/// the signal handler, so it must not allocate or take locks.
+pub(crate) fn guard<'a, R>(
+    locate: &'a dyn Fn(usize) -> Option<Region<'a>>,
+    f: impl FnOnce() -> R,
+) -> (R, Option<Trap>) {
+    install();
+    let active = Active {
+        locate,
+        trap: Cell::new(None),
+    };
+    // the handler only dereferences the pointer while `f` runs
+    let active_ptr = std::ptr::from_ref(&active).cast::<Active<'static>>();
+    let outer = Restore(ACTIVE.with(|cell| cell.replace(active_ptr)));
+    let result = f();
+    drop(outer);
+    (result, active.trap.get())
+}
+
+/// Puts back the `guard` a nested one replaced when dropped, so a panic in
+/// `f` does not leave the handler pointing at a dead frame.
+struct Restore(*const Active<'static>);
+
+impl Drop for Restore {
+    fn drop(&mut self) {
+        ACTIVE.with(|cell| cell.set(self.0));
+    }
+}
+
+fn install() {
+    static INSTALL: Once = Once::new();
+    INSTALL.call_once(|| unsafe {
+        let mut action: libc::sigaction = std::mem::zeroed();
+        action.sa_sigaction = handle as *const () as usize;
+        action.sa_flags = libc::SA_SIGINFO | libc::SA_ONSTACK;
+        libc::sigemptyset(&mut action.sa_mask);
+        let mut previous: [libc::sigaction; 3] = std::mem::zeroed();
+        for (&signal, previous) in SIGNALS.iter().zip(&mut previous) {
+            libc::sigaction(signal, &action, previous);
+        }
+        PREVIOUS.set(previous).ok();
+    });
+}
+
+extern "C" fn handle(signal: libc::c_int, info: *mut libc::siginfo_t, context: *mut libc::c_void) {
+    let active = ACTIVE.with(|cell| cell.get());
+    if !active.is_null() {
+        let active = unsafe { &*active };
+        let context = unsafe { &mut *(context as *mut libc::ucontext_t) };
+        let rip = &mut context.uc_mcontext.gregs[libc::REG_RIP as usize];
+        let address = *rip as usize;
+        if let Some(region) = (active.locate)(address) {
+            let start = region.start();
+            if let Some(pc) = region.map.pc_at(address - start) {
+                if active.trap.get().is_none() {
+                    active.trap.set(Some(Trap {
+                        signal,
+                        program: region.program,
+                        pc,
+                    }));
+                }
+                *rip = (start + region.map.trap_offset()) as libc::greg_t;
+                return;
+            }
+        }
+    }
+    forward(signal, info, context);
+}
+
+/// Hands a fault that is not ours to the handler installed before. A default
+/// or ignored disposition is restored and the faulting instruction re-run,
+/// so the process dies as it would have without us.
+fn forward(signal: libc::c_int, info: *mut libc::siginfo_t, context: *mut libc::c_void) {
+    let Some(previous) = PREVIOUS.get() else {
+        return;
+    };
+    let Some(i) = SIGNALS.iter().position(|&s| s == signal) else {
+        return;
+    };
+    let previous = &previous[i];
+    unsafe {
+        match previous.sa_sigaction {
+            libc::SIG_DFL | libc::SIG_IGN => {
+                libc::sigaction(signal, previous, std::ptr::null_mut());
+            }
+            handler if previous.sa_flags & libc::SA_SIGINFO != 0 => {
+                let handler: extern "C" fn(libc::c_int, *mut libc::siginfo_t, *mut libc::c_void) =
+                    std::mem::transmute(handler);
+                handler(signal, info, context);
+            }
+            handler => {
+                let handler: extern "C" fn(libc::c_int) = std::mem::transmute(handler);
+                handler(signal);
+            }
+        }
+    }
+}
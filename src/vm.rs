@@ -1,123 +1,671 @@
-use crate::instructions::opcodes::*;
+use crate::config::{Config, UnderflowPolicy};
+use crate::container::{Container, ContainerError, validate_bytecode};
+use crate::env::Environment;
+use crate::gas;
+use crate::instructions::{self, opcodes::*};
+use crate::runtime::{self, CALL_DEPTH_LIMIT, Log, MEMORY_SIZE, RETURN_STACK_LIMIT};
+use crate::status::Status;
 use std::collections::HashMap;
+use std::rc::Rc;
 
-#[derive(Default)]
 pub struct VM {
-    memory: HashMap<u8, u64>,
+    storage: HashMap<u8, u64>,
+    memory: Vec<u8>,
     stack: Vec<u64>,
+    gas_used: u64,
+    gas_limit: u64,
+    return_data: Vec<u8>,
+    logs: Vec<Log>,
+    programs: Vec<Rc<[u8]>>,
+    program_storage: Vec<HashMap<u8, u64>>,
+    transient: HashMap<u8, u64>,
+    program_transient: Vec<HashMap<u8, u64>>,
+    config: Config,
+}
+
+/// Per-call execution state; storage and transient storage live in the VM.
+struct Frame {
+    stack: Vec<u64>,
+    memory: Vec<u8>,
+    gas_used: u64,
+    gas_limit: u64,
+    stack_limit: usize,
+    return_data: Vec<u8>,
+}
+
+impl Frame {
+    fn new(gas_limit: u64, stack_limit: usize) -> Self {
+        Self {
+            stack: Vec::new(),
+            memory: vec![0; MEMORY_SIZE],
+            gas_used: 0,
+            gas_limit,
+            stack_limit,
+            return_data: Vec::new(),
+        }
+    }
+}
+
+impl Default for VM {
+    fn default() -> Self {
+        Self {
+            storage: HashMap::new(),
+            memory: vec![0; MEMORY_SIZE],
+            stack: Vec::new(),
+            gas_used: 0,
+            gas_limit: u64::MAX,
+            return_data: Vec::new(),
+            logs: Vec::new(),
+            programs: Vec::new(),
+            program_storage: Vec::new(),
+            transient: HashMap::new(),
+            program_transient: Vec::new(),
+            config: Config::default(),
+        }
+    }
 }
 
 impl VM {
-    pub fn interpret(&mut self, code: &[u8]) {
+    /// Registers `code` as a program other programs can CALL, with its own
+    /// storage, once it passes [`validate_bytecode`]. Returns its id.
+    pub fn deploy(&mut self, code: Vec<u8>) -> Result<u64, ContainerError> {
+        validate_bytecode(&code)?;
+        self.programs.push(code.into());
+        self.program_storage.push(HashMap::new());
+        self.program_transient.push(HashMap::new());
+        Ok((self.programs.len() - 1) as u64)
+    }
+
+    pub fn set_gas_limit(&mut self, gas_limit: u64) {
+        self.gas_limit = gas_limit;
+    }
+
+    pub fn set_config(&mut self, config: Config) {
+        self.config = config;
+    }
+
+    /// Runs `code` against the given call data and environment; the VM can
+    /// be reused with different inputs. Every run starts on an empty stack
+    /// and keeps the storage and memory of the last. Logs are only kept if
+    /// the run succeeds; transient storage is cleared either way.
+    pub fn interpret(&mut self, code: &[u8], calldata: &[u8], env: &Environment) -> Status {
+        self.run(code, &[], calldata, env, self.config.stack_limit)
+    }
+
+    /// Runs the code image of a validated container, with its data section.
+    /// Its declared max stack height is reserved up front and enforced: the
+    /// run halts with `StackOverflow` past it, or past the config's stack
+    /// limit if that is lower.
+    pub fn interpret_container(
+        &mut self,
+        container: &Container,
+        calldata: &[u8],
+        env: &Environment,
+    ) -> Status {
+        let stack_limit = self
+            .config
+            .stack_limit
+            .min(container.max_stack_height() as usize);
+        self.stack.reserve(stack_limit);
+        self.run(
+            container.code(),
+            container.data(),
+            calldata,
+            env,
+            stack_limit,
+        )
+    }
+
+    fn run(
+        &mut self,
+        code: &[u8],
+        data: &[u8],
+        calldata: &[u8],
+        env: &Environment,
+        stack_limit: usize,
+    ) -> Status {
+        self.logs.clear();
+        self.stack.clear();
+        let mut frame = Frame {
+            stack: std::mem::take(&mut self.stack),
+            memory: std::mem::take(&mut self.memory),
+            ..Frame::new(self.gas_limit, stack_limit)
+        };
+        let status = self.execute(code, data, calldata, env, None, &mut frame, 0);
+        self.stack = frame.stack;
+        self.memory = frame.memory;
+        self.gas_used = frame.gas_used;
+        self.return_data = frame.return_data;
+        if status != Status::Stopped {
+            self.logs.clear();
+        }
+        self.transient.clear();
+        for transient in &mut self.program_transient {
+            transient.clear();
+        }
+        status
+    }
+
+    /// Runs a CALL to program `id` with `input` as its call data. Deployed
+    /// programs are plain code without a data section. Returns
+    /// whether it succeeded, the gas to charge the caller and the output.
+    /// A failed callee consumes its whole gas limit and its storage and
+    /// transient storage writes and logs (including those of nested calls)
+    /// are rolled back.
+    fn call(
+        &mut self,
+        env: &Environment,
+        id: u64,
+        gas_limit: u64,
+        input: &[u8],
+        depth: usize,
+    ) -> (bool, u64, Vec<u8>) {
+        let Some(code) = usize::try_from(id)
+            .ok()
+            .and_then(|id| self.programs.get(id))
+        else {
+            return (false, 0, Vec::new());
+        };
+        if depth >= CALL_DEPTH_LIMIT {
+            return (false, 0, Vec::new());
+        }
+        let code = code.clone();
+        let snapshot = self.program_storage.clone();
+        let transient_snapshot = self.program_transient.clone();
+        let logs_len = self.logs.len();
+        let mut frame = Frame::new(gas_limit, self.config.stack_limit);
+        let callee_env = env.for_callee(id);
+        match self.execute(
+            &code,
+            &[],
+            input,
+            &callee_env,
+            Some(id as usize),
+            &mut frame,
+            depth + 1,
+        ) {
+            Status::Stopped => (true, frame.gas_used, frame.return_data),
+            _ => {
+                self.program_storage = snapshot;
+                self.program_transient = transient_snapshot;
+                self.logs.truncate(logs_len);
+                (false, gas_limit, Vec::new())
+            }
+        }
+    }
+
+    fn storage_of(&mut self, program: Option<usize>) -> &mut HashMap<u8, u64> {
+        match program {
+            Some(id) => &mut self.program_storage[id],
+            None => &mut self.storage,
+        }
+    }
+
+    fn transient_of(&mut self, program: Option<usize>) -> &mut HashMap<u8, u64> {
+        match program {
+            Some(id) => &mut self.program_transient[id],
+            None => &mut self.transient,
+        }
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    fn execute(
+        &mut self,
+        code: &[u8],
+        data: &[u8],
+        calldata: &[u8],
+        env: &Environment,
+        program: Option<usize>,
+        frame: &mut Frame,
+        depth: usize,
+    ) -> Status {
+        let entries = instructions::subroutine_entries(code);
+        let mut return_stack: Vec<usize> = Vec::new();
         let mut pc = 0;
         while pc < code.len() {
-            match code[pc] {
+            let op = code[pc];
+            frame.gas_used = frame.gas_used.saturating_add(gas::static_cost(op));
+            if frame.gas_used > frame.gas_limit {
+                frame.gas_used = frame.gas_limit;
+                return Status::OutOfGas;
+            }
+            let len = frame.stack.len();
+            let trap = self.config.underflow == UnderflowPolicy::Trap;
+            if trap && len < instructions::required_depth(op) {
+                frame.gas_used = frame.gas_limit;
+                return Status::StackUnderflow;
+            }
+            // under ZeroFill missing operands do not count as popped
+            let (pops, pushes) = instructions::stack_effect(op);
+            let new_len = len.saturating_sub(pops) + pushes;
+            if new_len > len && new_len > frame.stack_limit {
+                frame.gas_used = frame.gas_limit;
+                return Status::StackOverflow;
+            }
+            match op {
                 PUSH => {
                     let val = code[pc + 1] as u64;
-                    self.stack.push(val);
+                    frame.stack.push(val);
                     pc += 2;
                 }
                 SSTORE => {
                     let key = code[pc + 1];
-                    let val = self.stack.pop().unwrap_or(0);
-                    self.memory.insert(key, val);
+                    let val = frame.stack.pop().unwrap_or(0);
+                    self.storage_of(program).insert(key, val);
                     pc += 2;
                 }
                 SLOAD => {
                     let key = code[pc + 1];
-                    let val = *self.memory.get(&key).unwrap_or(&0);
-                    self.stack.push(val);
+                    let val = *self.storage_of(program).get(&key).unwrap_or(&0);
+                    frame.stack.push(val);
+                    pc += 2;
+                }
+                TSTORE => {
+                    let key = code[pc + 1];
+                    let val = frame.stack.pop().unwrap_or(0);
+                    self.transient_of(program).insert(key, val);
+                    pc += 2;
+                }
+                TLOAD => {
+                    let key = code[pc + 1];
+                    let val = *self.transient_of(program).get(&key).unwrap_or(&0);
+                    frame.stack.push(val);
                     pc += 2;
                 }
                 ADD => {
-                    let b = self.stack.pop().unwrap_or(0);
-                    let a = self.stack.pop().unwrap_or(0);
-                    self.stack.push(a.wrapping_add(b));
+                    let b = frame.stack.pop().unwrap_or(0);
+                    let a = frame.stack.pop().unwrap_or(0);
+                    frame.stack.push(a.wrapping_add(b));
                     pc += 1;
                 }
                 SUB => {
-                    let b = self.stack.pop().unwrap_or(0);
-                    let a = self.stack.pop().unwrap_or(0);
-                    self.stack.push(a.wrapping_sub(b));
+                    let b = frame.stack.pop().unwrap_or(0);
+                    let a = frame.stack.pop().unwrap_or(0);
+                    frame.stack.push(a.wrapping_sub(b));
                     pc += 1;
                 }
                 MUL => {
-                    let b = self.stack.pop().unwrap_or(0);
-                    let a = self.stack.pop().unwrap_or(0);
-                    self.stack.push(a.wrapping_mul(b));
+                    let b = frame.stack.pop().unwrap_or(0);
+                    let a = frame.stack.pop().unwrap_or(0);
+                    frame.stack.push(a.wrapping_mul(b));
                     pc += 1;
                 }
                 DIV => {
-                    let b = self.stack.pop().unwrap_or(1);
-                    let a = self.stack.pop().unwrap_or(0);
-                    self.stack.push(if b == 0 { 0 } else { a / b });
+                    let b = frame.stack.pop().unwrap_or(0);
+                    let a = frame.stack.pop().unwrap_or(0);
+                    frame.stack.push(a.checked_div(b).unwrap_or(0));
                     pc += 1;
                 }
                 MOD => {
-                    let b = self.stack.pop().unwrap_or(1);
-                    let a = self.stack.pop().unwrap_or(0);
-                    self.stack.push(if b == 0 { 0 } else { a % b });
+                    let b = frame.stack.pop().unwrap_or(0);
+                    let a = frame.stack.pop().unwrap_or(0);
+                    frame.stack.push(a.checked_rem(b).unwrap_or(0));
                     pc += 1;
                 }
                 EQ => {
-                    let b = self.stack.pop().unwrap_or(0);
-                    let a = self.stack.pop().unwrap_or(0);
-                    self.stack.push(if a == b { 1 } else { 0 });
+                    let b = frame.stack.pop().unwrap_or(0);
+                    let a = frame.stack.pop().unwrap_or(0);
+                    frame.stack.push(if a == b { 1 } else { 0 });
                     pc += 1;
                 }
                 LT => {
-                    let b = self.stack.pop().unwrap_or(0);
-                    let a = self.stack.pop().unwrap_or(0);
-                    self.stack.push(if a < b { 1 } else { 0 });
+                    let b = frame.stack.pop().unwrap_or(0);
+                    let a = frame.stack.pop().unwrap_or(0);
+                    frame.stack.push(if a < b { 1 } else { 0 });
                     pc += 1;
                 }
                 GT => {
-                    let b = self.stack.pop().unwrap_or(0);
-                    let a = self.stack.pop().unwrap_or(0);
-                    self.stack.push(if a > b { 1 } else { 0 });
+                    let b = frame.stack.pop().unwrap_or(0);
+                    let a = frame.stack.pop().unwrap_or(0);
+                    frame.stack.push(if a > b { 1 } else { 0 });
+                    pc += 1;
+                }
+                SDIV => {
+                    let b = frame.stack.pop().unwrap_or(0) as i64;
+                    let a = frame.stack.pop().unwrap_or(0) as i64;
+                    // i64::MIN / -1 wraps back to i64::MIN as in the EVM
+                    frame
+                        .stack
+                        .push(if b == 0 { 0 } else { a.wrapping_div(b) as u64 });
+                    pc += 1;
+                }
+                SMOD => {
+                    let b = frame.stack.pop().unwrap_or(0) as i64;
+                    let a = frame.stack.pop().unwrap_or(0) as i64;
+                    // the result takes the sign of the dividend
+                    frame
+                        .stack
+                        .push(if b == 0 { 0 } else { a.wrapping_rem(b) as u64 });
+                    pc += 1;
+                }
+                SLT => {
+                    let b = frame.stack.pop().unwrap_or(0) as i64;
+                    let a = frame.stack.pop().unwrap_or(0) as i64;
+                    frame.stack.push(if a < b { 1 } else { 0 });
+                    pc += 1;
+                }
+                SGT => {
+                    let b = frame.stack.pop().unwrap_or(0) as i64;
+                    let a = frame.stack.pop().unwrap_or(0) as i64;
+                    frame.stack.push(if a > b { 1 } else { 0 });
+                    pc += 1;
+                }
+                SIGNEXTEND => {
+                    let b = frame.stack.pop().unwrap_or(0);
+                    let x = frame.stack.pop().unwrap_or(0);
+                    // extend the sign bit of byte `b` (counted from the least
+                    // significant end); b >= 7 leaves the word unchanged
+                    let val = if b < 7 {
+                        let shift = 56 - 8 * b as u32;
+                        (((x << shift) as i64) >> shift) as u64
+                    } else {
+                        x
+                    };
+                    frame.stack.push(val);
                     pc += 1;
                 }
                 AND => {
-                    let b = self.stack.pop().unwrap_or(0);
-                    let a = self.stack.pop().unwrap_or(0);
-                    self.stack.push(a & b);
+                    let b = frame.stack.pop().unwrap_or(0);
+                    let a = frame.stack.pop().unwrap_or(0);
+                    frame.stack.push(a & b);
                     pc += 1;
                 }
                 OR => {
-                    let b = self.stack.pop().unwrap_or(0);
-                    let a = self.stack.pop().unwrap_or(0);
-                    self.stack.push(a | b);
+                    let b = frame.stack.pop().unwrap_or(0);
+                    let a = frame.stack.pop().unwrap_or(0);
+                    frame.stack.push(a | b);
                     pc += 1;
                 }
                 XOR => {
-                    let b = self.stack.pop().unwrap_or(0);
-                    let a = self.stack.pop().unwrap_or(0);
-                    self.stack.push(a ^ b);
+                    let b = frame.stack.pop().unwrap_or(0);
+                    let a = frame.stack.pop().unwrap_or(0);
+                    frame.stack.push(a ^ b);
+                    pc += 1;
+                }
+                SHL => {
+                    let shift = frame.stack.pop().unwrap_or(0);
+                    let val = frame.stack.pop().unwrap_or(0);
+                    frame.stack.push(if shift < 64 { val << shift } else { 0 });
+                    pc += 1;
+                }
+                SHR => {
+                    let shift = frame.stack.pop().unwrap_or(0);
+                    let val = frame.stack.pop().unwrap_or(0);
+                    frame.stack.push(if shift < 64 { val >> shift } else { 0 });
+                    pc += 1;
+                }
+                SAR => {
+                    let shift = frame.stack.pop().unwrap_or(0);
+                    let val = frame.stack.pop().unwrap_or(0) as i64;
+                    // shifting by the word width or more leaves only the sign
+                    frame.stack.push((val >> shift.min(63)) as u64);
+                    pc += 1;
+                }
+                NOT => {
+                    let a = frame.stack.pop().unwrap_or(0);
+                    frame.stack.push(!a);
+                    pc += 1;
+                }
+                ISZERO => {
+                    let a = frame.stack.pop().unwrap_or(0);
+                    frame.stack.push(if a == 0 { 1 } else { 0 });
+                    pc += 1;
+                }
+                BYTE => {
+                    let i = frame.stack.pop().unwrap_or(0);
+                    let x = frame.stack.pop().unwrap_or(0);
+                    // byte 0 is the most significant one
+                    frame.stack.push(if i < 8 {
+                        (x >> (8 * (7 - i))) & 0xff
+                    } else {
+                        0
+                    });
+                    pc += 1;
+                }
+                ADDMOD => {
+                    let a = frame.stack.pop().unwrap_or(0) as u128;
+                    let b = frame.stack.pop().unwrap_or(0) as u128;
+                    let n = frame.stack.pop().unwrap_or(0) as u128;
+                    frame.stack.push((a + b).checked_rem(n).unwrap_or(0) as u64);
+                    pc += 1;
+                }
+                MULMOD => {
+                    let a = frame.stack.pop().unwrap_or(0) as u128;
+                    let b = frame.stack.pop().unwrap_or(0) as u128;
+                    let n = frame.stack.pop().unwrap_or(0) as u128;
+                    frame.stack.push((a * b).checked_rem(n).unwrap_or(0) as u64);
+                    pc += 1;
+                }
+                EXP => {
+                    let mut base = frame.stack.pop().unwrap_or(0);
+                    let mut exponent = frame.stack.pop().unwrap_or(0);
+                    frame.gas_used = frame.gas_used.saturating_add(gas::exp_cost(exponent));
+                    // square-and-multiply
+                    let mut result = 1u64;
+                    while exponent != 0 {
+                        if exponent & 1 == 1 {
+                            result = result.wrapping_mul(base);
+                        }
+                        base = base.wrapping_mul(base);
+                        exponent >>= 1;
+                    }
+                    frame.stack.push(result);
                     pc += 1;
                 }
                 DUP => {
-                    let top = self.stack.last().copied().unwrap_or(0);
-                    self.stack.push(top);
+                    let top = frame.stack.last().copied().unwrap_or(0);
+                    frame.stack.push(top);
                     pc += 1;
                 }
                 SWAP => {
-                    if self.stack.len() >= 2 {
-                        let len = self.stack.len();
-                        self.stack.swap(len - 1, len - 2);
+                    if frame.stack.len() >= 2 {
+                        let len = frame.stack.len();
+                        frame.stack.swap(len - 1, len - 2);
+                    }
+                    pc += 1;
+                }
+                POP => {
+                    frame.stack.pop();
+                    pc += 1;
+                }
+                DUP1..=DUP16 => {
+                    let n = (op - DUP1 + 1) as usize;
+                    let len = frame.stack.len();
+                    let val = if len >= n { frame.stack[len - n] } else { 0 };
+                    frame.stack.push(val);
+                    pc += 1;
+                }
+                SWAP1..=SWAP16 => {
+                    let n = (op - SWAP1 + 1) as usize;
+                    let len = frame.stack.len();
+                    if len > n {
+                        frame.stack.swap(len - 1, len - 1 - n);
+                    }
+                    pc += 1;
+                }
+                CALLDATALOAD => {
+                    let offset = frame.stack.pop().unwrap_or(0);
+                    frame.stack.push(runtime::load_word(calldata, offset));
+                    pc += 1;
+                }
+                DATALOAD => {
+                    let offset = frame.stack.pop().unwrap_or(0);
+                    frame.stack.push(runtime::load_word(data, offset));
+                    pc += 1;
+                }
+                DATALOADN => {
+                    let offset = u16::from_be_bytes([code[pc + 1], code[pc + 2]]);
+                    frame.stack.push(runtime::load_word(data, offset as u64));
+                    pc += 3;
+                }
+                DATASIZE => {
+                    frame.stack.push(data.len() as u64);
+                    pc += 1;
+                }
+                DATACOPY => {
+                    let dest = frame.stack.pop().unwrap_or(0);
+                    let offset = frame.stack.pop().unwrap_or(0);
+                    let size = frame.stack.pop().unwrap_or(0);
+                    runtime::copy_to_memory(&mut frame.memory, dest, data, offset, size);
+                    pc += 1;
+                }
+                CALLDATASIZE => {
+                    frame.stack.push(calldata.len() as u64);
+                    pc += 1;
+                }
+                CALLDATACOPY => {
+                    let dest = frame.stack.pop().unwrap_or(0);
+                    let offset = frame.stack.pop().unwrap_or(0);
+                    let size = frame.stack.pop().unwrap_or(0);
+                    runtime::copy_to_memory(&mut frame.memory, dest, calldata, offset, size);
+                    pc += 1;
+                }
+                MLOAD => {
+                    let offset = frame.stack.pop().unwrap_or(0);
+                    frame.stack.push(runtime::load_word(&frame.memory, offset));
+                    pc += 1;
+                }
+                MSTORE => {
+                    let offset = frame.stack.pop().unwrap_or(0);
+                    let val = frame.stack.pop().unwrap_or(0);
+                    runtime::store_word(&mut frame.memory, offset, val);
+                    pc += 1;
+                }
+                BEGINSUB => return Status::WalkIntoSubroutine,
+                CALLSUB => {
+                    let target = u16::from_be_bytes([code[pc + 1], code[pc + 2]]) as usize;
+                    if !entries.get(target).copied().unwrap_or(false) {
+                        return Status::InvalidJump;
                     }
+                    if return_stack.len() == RETURN_STACK_LIMIT {
+                        return Status::ReturnStackOverflow;
+                    }
+                    return_stack.push(pc + 3);
+                    // enter past the BEGINSUB, which only executes this way
+                    frame.gas_used = frame.gas_used.saturating_add(gas::static_cost(BEGINSUB));
+                    pc = target + 1;
+                }
+                RETSUB => match return_stack.pop() {
+                    Some(ret) => pc = ret,
+                    None => return Status::ReturnStackUnderflow,
+                },
+                CALL => {
+                    let gas_limit = frame.stack.pop().unwrap_or(0);
+                    let id = frame.stack.pop().unwrap_or(0);
+                    let in_offset = frame.stack.pop().unwrap_or(0);
+                    let in_size = frame.stack.pop().unwrap_or(0);
+                    let out_offset = frame.stack.pop().unwrap_or(0);
+                    let out_size = frame.stack.pop().unwrap_or(0);
+                    let input = runtime::memory_slice(&frame.memory, in_offset, in_size).to_vec();
+                    let (success, gas, output) = self.call(env, id, gas_limit, &input, depth);
+                    frame.gas_used = frame.gas_used.saturating_add(gas);
+                    runtime::copy_return_data(&mut frame.memory, out_offset, out_size, &output);
+                    frame.stack.push(success as u64);
+                    pc += 1;
+                }
+                RETURN => {
+                    let offset = frame.stack.pop().unwrap_or(0);
+                    let size = frame.stack.pop().unwrap_or(0);
+                    frame.return_data = runtime::memory_slice(&frame.memory, offset, size).to_vec();
+                    break;
+                }
+                LOG0..=LOG4 => {
+                    let offset = frame.stack.pop().unwrap_or(0);
+                    let size = frame.stack.pop().unwrap_or(0);
+                    let topics = (0..op - LOG0)
+                        .map(|_| frame.stack.pop().unwrap_or(0))
+                        .collect();
+                    frame.gas_used = frame.gas_used.saturating_add(gas::log_cost(size));
+                    let data = runtime::memory_slice(&frame.memory, offset, size).to_vec();
+                    self.logs.push(Log { topics, data });
+                    pc += 1;
+                }
+                KECCAK256 => {
+                    let offset = frame.stack.pop().unwrap_or(0);
+                    let size = frame.stack.pop().unwrap_or(0);
+                    frame.gas_used = frame.gas_used.saturating_add(gas::keccak_cost(size));
+                    frame
+                        .stack
+                        .push(runtime::hash_memory(&frame.memory, offset, size));
+                    pc += 1;
+                }
+                CALLER => {
+                    frame.stack.push(env.caller);
+                    pc += 1;
+                }
+                ADDRESS => {
+                    frame.stack.push(env.address);
+                    pc += 1;
+                }
+                CALLVALUE => {
+                    frame.stack.push(env.callvalue);
+                    pc += 1;
+                }
+                NUMBER => {
+                    frame.stack.push(env.number);
+                    pc += 1;
+                }
+                TIMESTAMP => {
+                    frame.stack.push(env.timestamp);
+                    pc += 1;
+                }
+                CHAINID => {
+                    frame.stack.push(env.chain_id);
+                    pc += 1;
+                }
+                GAS => {
+                    frame.stack.push(frame.gas_limit - frame.gas_used);
+                    pc += 1;
+                }
+                PC => {
+                    frame.stack.push(pc as u64);
                     pc += 1;
                 }
                 STOP => break,
                 _ => panic!("invalid opcode: {}", code[pc]),
             }
         }
+        // dynamic gas of the last instruction has not been checked yet
+        if frame.gas_used > frame.gas_limit {
+            frame.gas_used = frame.gas_limit;
+            return Status::OutOfGas;
+        }
+        Status::Stopped
     }
 
     pub fn stack(&self) -> &[u64] {
         &self.stack
     }
 
-    pub fn memory(&self) -> &HashMap<u8, u64> {
+    pub fn storage(&self) -> &HashMap<u8, u64> {
+        &self.storage
+    }
+
+    pub fn storage_mut(&mut self) -> &mut HashMap<u8, u64> {
+        &mut self.storage
+    }
+
+    pub fn memory(&self) -> &[u8] {
         &self.memory
     }
+
+    /// Gas used by the last call to `interpret`.
+    pub fn gas_used(&self) -> u64 {
+        self.gas_used
+    }
+
+    /// Output of the last call to `interpret`, set by RETURN.
+    pub fn return_data(&self) -> &[u8] {
+        &self.return_data
+    }
+
+    /// Logs emitted by the last successful call to `interpret`.
+    pub fn logs(&self) -> &[Log] {
+        &self.logs
+    }
+
+    /// Storage of a deployed program.
+    pub fn program_storage(&self, id: u64) -> &HashMap<u8, u64> {
+        &self.program_storage[id as usize]
+    }
 }
@@ -0,0 +1,104 @@
+//! The command line: its exit codes and how it checks its options.
+
+use std::path::PathBuf;
+use std::process::{Command, Output};
+
+fn poc_jit(args: &[&str]) -> Output {
+    Command::new(env!("CARGO_BIN_EXE_poc-jit"))
+        .args(args)
+        .output()
+        .unwrap()
+}
+
+/// A program file of this test process, removed when dropped.
+struct ProgramFile(PathBuf);
+
+impl ProgramFile {
+    fn new(name: &str, contents: &str) -> Self {
+        let path = std::env::temp_dir().join(format!("poc-jit-{}-{name}", std::process::id()));
+        std::fs::write(&path, contents).unwrap();
+        ProgramFile(path)
+    }
+
+    fn path(&self) -> &str {
+        self.0.to_str().unwrap()
+    }
+}
+
+impl Drop for ProgramFile {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_file(&self.0);
+    }
+}
+
+fn stdout(output: &Output) -> String {
+    String::from_utf8(output.stdout.clone()).unwrap()
+}
+
+fn stderr(output: &Output) -> String {
+    String::from_utf8(output.stderr.clone()).unwrap()
+}
+
+#[test]
+fn run_and_compare() {
+    // PUSH 1, PUSH 2, STOP
+    let file = ProgramFile::new("two.hex", "0301 0302 ff\n");
+    let path = file.path();
+    let output = poc_jit(&["run", "--engine", "interp", path]);
+    assert!(output.status.success());
+    assert!(stdout(&output).contains("status:           Stopped"));
+
+    let output = poc_jit(&["compare", path]);
+    assert!(output.status.success());
+    assert!(stdout(&output).ends_with("the engines agree\n"));
+
+    // a limit the program overflows is still one both engines agree on
+    let output = poc_jit(&["compare", "--stack-limit", "1", path]);
+    assert!(output.status.success());
+    assert!(stdout(&output).contains("StackOverflow"));
+}
+
+#[test]
+fn stack_limit_is_checked() {
+    let file = ProgramFile::new("one.hex", "0301 ff\n");
+    let path = file.path();
+    let output = poc_jit(&["run", "--stack-limit", "65536", path]);
+    assert!(output.status.success(), "{}", stderr(&output));
+
+    for limit in ["65537", "18446744073709551615"] {
+        let output = poc_jit(&["run", "--stack-limit", limit, path]);
+        assert_eq!(output.status.code(), Some(2));
+        assert!(stderr(&output).starts_with("poc-jit: --stack-limit must be at most 65536\n"));
+    }
+}
+
+#[test]
+fn exit_codes() {
+    let output = poc_jit(&["fuzz", "--seed", "1", "--cases", "10", "--len", "20"]);
+    assert!(output.status.success());
+    assert!(stdout(&output).ends_with("all 10 cases agree\n"));
+
+    // usage errors exit with 2, other errors with 1
+    for args in [&["frobnicate"][..], &[], &["fuzz", "--len", "0"]] {
+        assert_eq!(poc_jit(args).status.code(), Some(2), "{args:?}");
+    }
+    let output = poc_jit(&[
+        "run",
+        ProgramFile::new("undefined.hex", "0301 ee ff\n").path(),
+    ]);
+    assert_eq!(output.status.code(), Some(1));
+    let output = poc_jit(&["run", "/nonexistent/program.hex"]);
+    assert_eq!(output.status.code(), Some(1));
+}
+
+#[test]
+fn asm_and_disasm() {
+    let file = ProgramFile::new("program.asm", "PUSH 1\nPUSH 2\nADD\nSTOP\n");
+    let path = file.path();
+    let output = poc_jit(&["asm", path]);
+    assert!(output.status.success(), "{}", stderr(&output));
+    let hex = stdout(&output);
+    let output = poc_jit(&["disasm", ProgramFile::new("program.hex", &hex).path()]);
+    assert!(output.status.success(), "{}", stderr(&output));
+    assert!(stdout(&output).contains("ADD"));
+}
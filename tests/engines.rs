@@ -0,0 +1,1007 @@
+//! Differential tests: programs run on the interpreter and on the JIT must
+//! end in the same state.
+
+use poc_jit::container::MAX_STACK_HEIGHT;
+use poc_jit::instructions::{immediate_len, is_defined, opcodes::*, required_depth};
+use poc_jit::{
+    CALL_DEPTH_LIMIT, CompiledProgram, Config, Container, ContainerError, Context, Environment,
+    Log, MEMORY_SIZE, RETURN_STACK_LIMIT, Status, UnderflowPolicy, VM,
+};
+use poc_jit::{gas, keccak};
+
+/// State a run leaves behind.
+#[derive(Clone, Debug, PartialEq, Eq)]
+struct Final {
+    status: Status,
+    gas_used: u64,
+    stack: Vec<u64>,
+    storage: Vec<(usize, u64)>,
+    logs: Vec<Log>,
+    /// Storage of each deployed program.
+    deployed: Vec<Vec<(usize, u64)>>,
+}
+
+impl Final {
+    /// The state a caller can observe after a run that did not stop: how
+    /// far the stack got is unspecified.
+    fn observable(&self) -> Final {
+        if self.status == Status::Stopped {
+            self.clone()
+        } else {
+            Final {
+                stack: Vec::new(),
+                ..self.clone()
+            }
+        }
+    }
+}
+
+fn interpret(code: &[u8], deployed: &[&[u8]], gas_limit: u64, config: Config) -> Final {
+    let mut vm = VM::default();
+    vm.set_gas_limit(gas_limit);
+    vm.set_config(config);
+    for code in deployed {
+        vm.deploy(code.to_vec()).unwrap();
+    }
+    let status = vm.interpret(code, &[], &Environment::default());
+    let sorted = |storage: &std::collections::HashMap<u8, u64>| {
+        let mut slots: Vec<(usize, u64)> = storage
+            .iter()
+            .filter(|&(_, &v)| v != 0)
+            .map(|(&k, &v)| (k as usize, v))
+            .collect();
+        slots.sort();
+        slots
+    };
+    Final {
+        status,
+        gas_used: vm.gas_used(),
+        stack: vm.stack().to_vec(),
+        storage: sorted(vm.storage()),
+        logs: vm.logs().to_vec(),
+        deployed: (0..deployed.len() as u64)
+            .map(|id| sorted(vm.program_storage(id)))
+            .collect(),
+    }
+}
+
+fn compile(code: &[u8], deployed: &[&[u8]], gas_limit: u64, config: Config) -> Final {
+    let program = CompiledProgram::new(code, &config).unwrap();
+    let mut ctx = Context::new(config);
+    for code in deployed {
+        ctx.host_mut().deploy(code).unwrap();
+    }
+    ctx.gas_limit = gas_limit;
+    let outcome = program.run_guarded(&mut ctx).unwrap();
+    let non_zero = |slots: &[u64]| -> Vec<(usize, u64)> {
+        slots
+            .iter()
+            .copied()
+            .enumerate()
+            .filter(|&(_, v)| v != 0)
+            .collect()
+    };
+    let host = ctx.host();
+    Final {
+        status: outcome.status,
+        gas_used: outcome.gas_used,
+        stack: host.stack(),
+        storage: non_zero(ctx.storage()),
+        logs: host.logs().to_vec(),
+        deployed: (0..deployed.len() as u64)
+            .map(|id| non_zero(host.storage(id)))
+            .collect(),
+    }
+}
+
+/// Runs `code` on both engines and checks they end in the same state.
+fn assert_same(code: &[u8], deployed: &[&[u8]], gas_limit: u64, config: Config) -> Final {
+    let interpreted = interpret(code, deployed, gas_limit, config);
+    let compiled = compile(code, deployed, gas_limit, config);
+    assert_eq!(
+        interpreted.observable(),
+        compiled.observable(),
+        "code {code:02x?} with gas limit {gas_limit} and {config:?}"
+    );
+    interpreted
+}
+
+#[test]
+fn out_of_gas_within_a_block() {
+    let callee: &[u8] = &[PUSH, 5, SSTORE, 0, STOP];
+    // a single block with storage writes before and after a CALL and a log
+    let code = [
+        &[PUSH, 1, SSTORE, 0][..],
+        &[PUSH, 0, PUSH, 0, PUSH, 0, PUSH, 0, PUSH, 0, PUSH, 200, CALL],
+        &[PUSH, 2, SSTORE, 1],
+        &[PUSH, 7, PUSH, 0, PUSH, 0, LOG0 + 1],
+        &[PUSH, 3, SSTORE, 2, STOP],
+    ]
+    .concat();
+    let full = interpret(&code, &[callee], u64::MAX, Config::default());
+    assert_eq!(full.status, Status::Stopped);
+    for gas_limit in 0..=full.gas_used {
+        let last = assert_same(&code, &[callee], gas_limit, Config::default());
+        assert_eq!(last.status == Status::Stopped, gas_limit == full.gas_used);
+    }
+
+    // the case the JIT used to run to the end of the block
+    let code = [PUSH, 1, SSTORE, 0, PUSH, 2, SSTORE, 1, STOP];
+    let last = assert_same(&code, &[], 150, Config::default());
+    assert_eq!(last.status, Status::OutOfGas);
+    assert_eq!(last.storage, [(0, 1)]);
+
+    // the dynamic gas of a log is charged after the static gas before it
+    let code = [PUSH, 0, NOT, PUSH, 0, LOG0, STOP];
+    let last = assert_same(&code, &[], 1_000_000, Config::default());
+    assert_eq!(last.status, Status::OutOfGas);
+}
+
+/// Code pushing `value`, a byte at a time since PUSH takes a 1-byte
+/// immediate.
+fn push(value: u64) -> Vec<u8> {
+    let mut code = vec![PUSH, (value >> 56) as u8];
+    for byte in value.to_be_bytes()[1..].iter().copied() {
+        code.extend([PUSH, 8, SHL, PUSH, byte, OR]);
+    }
+    code
+}
+
+/// Runs `op` on `operand` and the `top` above it, returning what it leaves.
+fn binary(op: u8, operand: u64, top: u64) -> u64 {
+    let code = [push(operand), push(top), vec![op, STOP]].concat();
+    let last = assert_same(&code, &[], 1_000_000, Config::default());
+    assert_eq!(last.status, Status::Stopped);
+    last.stack[0]
+}
+
+#[test]
+fn shifts() {
+    let operands = [
+        0,
+        1,
+        0x80,
+        0x0123_4567_89ab_cdef,
+        1 << 63,
+        0xfedc_ba98_7654_3210,
+        u64::MAX,
+    ];
+    let shifts = [0, 1, 4, 63, 64, 65, 255, 256, 1 << 32, u64::MAX];
+    for operand in operands {
+        for shift in shifts {
+            binary(SHL, operand, shift);
+            binary(SHR, operand, shift);
+            binary(SAR, operand, shift);
+        }
+    }
+    assert_eq!(binary(SHL, 1, 63), 1 << 63);
+    assert_eq!(binary(SHL, 1, 64), 0);
+    assert_eq!(binary(SHR, u64::MAX, 64), 0);
+    assert_eq!(binary(SAR, 1 << 63, 4), 0xf8 << 56);
+    assert_eq!(binary(SAR, 1 << 63, 64), u64::MAX);
+    assert_eq!(binary(SAR, -2i64 as u64, u64::MAX), u64::MAX);
+    assert_eq!(binary(SAR, 0x7f << 56, 64), 0);
+}
+
+#[test]
+fn byte() {
+    let value: u64 = 0x0123_4567_89ab_cdef;
+    for index in [0, 1, 6, 7, 8, 9, 31, 255, 1 << 32, u64::MAX] {
+        let expected = if index < 8 {
+            value.to_be_bytes()[index as usize] as u64
+        } else {
+            0
+        };
+        assert_eq!(binary(BYTE, value, index), expected, "byte {index}");
+    }
+}
+
+#[test]
+fn division() {
+    let min = i64::MIN as u64;
+    let minus = |value: i64| value as u64;
+    // the quotient that does not fit wraps, and its remainder is 0
+    assert_eq!(binary(SDIV, min, minus(-1)), min);
+    assert_eq!(binary(SMOD, min, minus(-1)), 0);
+    for op in [DIV, MOD, SDIV, SMOD] {
+        for operand in [0, 7, min, u64::MAX] {
+            assert_eq!(binary(op, operand, 0), 0, "{op:02x} of {operand} by 0");
+        }
+    }
+    assert_eq!(binary(DIV, u64::MAX, 2), u64::MAX / 2);
+    assert_eq!(binary(MOD, u64::MAX, 10), 5);
+    assert_eq!(binary(SDIV, minus(-7), 2), minus(-3));
+    // the remainder takes the sign of the dividend
+    assert_eq!(binary(SMOD, minus(-7), 3), minus(-1));
+    assert_eq!(binary(SMOD, 7, minus(-3)), 1);
+    assert_eq!(binary(SMOD, minus(-7), minus(-3)), minus(-1));
+}
+
+#[test]
+fn signed_comparisons() {
+    let minus = |value: i64| value as u64;
+    for (a, b) in [
+        (minus(-1), 0),
+        (i64::MIN as u64, i64::MAX as u64),
+        (1, 2),
+        (minus(-2), minus(-1)),
+    ] {
+        assert_eq!(binary(SLT, a, b), 1, "{a:x} < {b:x}");
+        assert_eq!(binary(SGT, a, b), 0, "{a:x} > {b:x}");
+        assert_eq!(binary(SLT, b, a), 0, "{b:x} < {a:x}");
+        assert_eq!(binary(SGT, b, a), 1, "{b:x} > {a:x}");
+    }
+    assert_eq!(binary(SLT, minus(-1), minus(-1)), 0);
+    assert_eq!(binary(SGT, minus(-1), minus(-1)), 0);
+    // the unsigned comparisons disagree
+    assert_eq!(binary(LT, minus(-1), 0), 0);
+}
+
+#[test]
+fn signextend() {
+    for (value, b, expected) in [
+        (0xff, 0, u64::MAX),
+        (0x7f, 0, 0x7f),
+        (0x1234_5680, 0, (u64::MAX << 8) | 0x80),
+        (0x0080_0000_0000_0000, 6, 0xff80_0000_0000_0000),
+        (0x1f7f_0000_0000_0000, 6, 0x007f_0000_0000_0000),
+        (0x8000_0000_0000_0000, 7, 0x8000_0000_0000_0000),
+        (0x1234, 7, 0x1234),
+        (0xff, 255, 0xff),
+        (0xff, u64::MAX, 0xff),
+    ] {
+        assert_eq!(
+            binary(SIGNEXTEND, value, b),
+            expected,
+            "byte {b} of {value:x}"
+        );
+    }
+}
+
+/// Runs `op` on `a` at the top, `b` below it and the modulus `n` below
+/// both, returning what it leaves.
+fn modular(op: u8, a: u64, b: u64, n: u64) -> u64 {
+    let code = [push(n), push(b), push(a), vec![op, STOP]].concat();
+    let last = assert_same(&code, &[], 1_000_000, Config::default());
+    assert_eq!(last.status, Status::Stopped);
+    last.stack[0]
+}
+
+#[test]
+fn addmod_and_mulmod() {
+    let max = u64::MAX;
+    // the sum and product are taken before the modulus, so they do not wrap
+    assert_eq!(
+        modular(ADDMOD, max, max, 10),
+        ((2 * max as u128) % 10) as u64
+    );
+    assert_eq!(modular(ADDMOD, max, 1, max), 1);
+    assert_eq!(
+        modular(MULMOD, max, max, 7),
+        ((max as u128 * max as u128) % 7) as u64
+    );
+    assert_eq!(modular(MULMOD, 1 << 32, 1 << 32, max), 1);
+    for op in [ADDMOD, MULMOD] {
+        assert_eq!(modular(op, max, max, 0), 0);
+        assert_eq!(modular(op, 5, 9, 0), 0);
+        assert_eq!(modular(op, max, max, 1), 0);
+        assert_eq!(modular(op, 5, 9, 1), 0);
+    }
+    assert_eq!(modular(ADDMOD, 5, 9, 4), 2);
+    assert_eq!(modular(MULMOD, 5, 9, 4), 1);
+}
+
+#[test]
+fn exp() {
+    // `binary` leaves the base on top of the exponent
+    let exp = |base: u64, exponent: u64| binary(EXP, exponent, base);
+    assert_eq!(exp(0, 0), 1);
+    assert_eq!(exp(u64::MAX, 0), 1);
+    assert_eq!(exp(3, 4), 81);
+    assert_eq!(exp(2, 63), 1 << 63);
+    assert_eq!(exp(2, 64), 0);
+    assert_eq!(exp(3, 0x1_0001), 3u64.wrapping_pow(0x1_0001));
+    assert_eq!(exp(u64::MAX, u64::MAX), u64::MAX);
+
+    // the pushes cost the same whatever the exponent, so the difference is
+    // the exponent's dynamic cost
+    let gas_used = |exponent: u64| {
+        let code = [push(exponent), push(3), vec![EXP, STOP]].concat();
+        assert_same(&code, &[], 1_000_000, Config::default()).gas_used
+    };
+    let base = gas_used(0);
+    assert_eq!(gas::exp_cost(0), 0);
+    assert_eq!(gas::exp_cost(0x1_0001), 3 * gas::EXP_BYTE_COST);
+    for exponent in [1, 0xff, 0x100, 0x1_0001, 1 << 63, u64::MAX] {
+        assert_eq!(
+            gas_used(exponent),
+            base + gas::exp_cost(exponent),
+            "{exponent:x}"
+        );
+    }
+}
+
+/// KECCAK256 of memory, which only hashes the part of the range inside
+/// memory but charges for every word of it.
+#[test]
+fn keccak256() {
+    let hash =
+        |bytes: &[u8]| u64::from_be_bytes(keccak::keccak256(bytes)[24..].try_into().unwrap());
+    let last = |offset: u64, size: u64| {
+        let code = [
+            &[PUSH, 0xAB, PUSH, 0, MSTORE][..],
+            &push(size),
+            &push(offset),
+            &[KECCAK256, STOP],
+        ]
+        .concat();
+        let last = assert_same(&code, &[], 1_000_000, Config::default());
+        assert_eq!(last.status, Status::Stopped, "{size} bytes at {offset}");
+        last
+    };
+    let end = MEMORY_SIZE as u64;
+    let mut word = [0; 8];
+    word[7] = 0xAB;
+    assert_eq!(last(0, 8).stack[0], hash(&word));
+    assert_eq!(last(4, 4).stack[0], hash(&word[4..]));
+    assert_eq!(last(end - 4, 64).stack[0], hash(&[0; 4]));
+    assert_eq!(last(end, 64).stack[0], hash(&[]));
+    assert_eq!(last(u64::MAX, 64).stack[0], hash(&[]));
+    assert_eq!(
+        last(0, 2 * end).stack[0],
+        hash(&[&word[..], &[0; MEMORY_SIZE - 8]].concat())
+    );
+
+    let base = last(0, 0).gas_used;
+    for size in [1, 31, 32, 33, 64, 65, 4 * MEMORY_SIZE as u64] {
+        assert_eq!(
+            last(0, size).gas_used,
+            base + gas::keccak_cost(size),
+            "{size} bytes"
+        );
+        assert_eq!(last(end - 4, size).gas_used, base + gas::keccak_cost(size));
+    }
+    assert_eq!(gas::keccak_cost(33), 2 * gas::KECCAK_WORD_COST);
+}
+
+#[test]
+fn not_and_iszero() {
+    for value in [0, 1, 2, 1 << 63, u64::MAX] {
+        let unary = |op| {
+            let code = [push(value), vec![op, STOP]].concat();
+            assert_same(&code, &[], 1_000_000, Config::default()).stack[0]
+        };
+        assert_eq!(unary(NOT), !value);
+        assert_eq!(unary(ISZERO), (value == 0) as u64);
+    }
+}
+
+/// Every instruction on a stack holding fewer items than it needs, then
+/// exactly one, under both underflow policies.
+#[test]
+fn underflow() {
+    for op in (0..=255).filter(|&op| is_defined(op)) {
+        for depth in 0..2 {
+            let mut code = [PUSH, 3].repeat(depth);
+            code.push(op);
+            if op == CALLSUB {
+                let target = (code.len() + 3) as u16;
+                code.extend(target.to_be_bytes());
+                code.extend([STOP, BEGINSUB, RETSUB]);
+            } else {
+                code.extend(vec![0; immediate_len(op)]);
+                code.push(STOP);
+            }
+            for underflow in [UnderflowPolicy::ZeroFill, UnderflowPolicy::Trap] {
+                let config = Config {
+                    underflow,
+                    ..Config::default()
+                };
+                let interpreted = interpret(&code, &[], 1_000_000, config);
+                let compiled = compile(&code, &[], 1_000_000, config);
+                assert_eq!(interpreted, compiled, "code {code:02x?} with {config:?}");
+                let short = depth < required_depth(op);
+                if underflow == UnderflowPolicy::Trap {
+                    assert_eq!(
+                        interpreted.status == Status::StackUnderflow,
+                        short,
+                        "{code:02x?}"
+                    );
+                }
+            }
+        }
+    }
+}
+
+/// A container's declared max stack height is its stack limit.
+#[test]
+fn max_stack_height() {
+    let code = [PUSH, 1, PUSH, 2, PUSH, 3, STOP];
+    for (height, status) in [(2, Status::StackOverflow), (3, Status::Stopped)] {
+        let bytes = Container::encode(&[&code], &[], height);
+        let container = Container::parse(&bytes).unwrap();
+        let config = Config::default();
+
+        let mut vm = VM::default();
+        vm.set_gas_limit(1_000_000);
+        assert_eq!(
+            vm.interpret_container(&container, &[], &Environment::default()),
+            status
+        );
+
+        let program = CompiledProgram::from_container(&container, &config);
+        let mut ctx = Context::new(config);
+        ctx.gas_limit = 1_000_000;
+        let outcome = program.run_guarded(&mut ctx).unwrap();
+        assert_eq!((outcome.status, outcome.gas_used), (status, vm.gas_used()));
+        if status == Status::Stopped {
+            assert_eq!(ctx.host().stack(), vm.stack());
+        }
+    }
+}
+
+/// One VM and one context running the same programs one after the other
+/// keep the same state between runs.
+#[test]
+fn reused_engines() {
+    let config = Config::default();
+    let mut vm = VM::default();
+    vm.set_gas_limit(1_000_000);
+    let mut ctx = Context::new(config);
+    ctx.gas_limit = 1_000_000;
+    let programs: [&[u8]; 5] = [
+        &[PUSH, 1, STOP],
+        &[PUSH, 1, STOP],
+        &[PUSH, 9, DUP1, SSTORE, 0, PUSH, 0, MSTORE, STOP],
+        &[PUSH, 1, POP, POP, STOP],
+        &[SLOAD, 0, PUSH, 0, MLOAD, PUSH, 1, STOP],
+    ];
+    for code in programs {
+        let status = vm.interpret(code, &[], &Environment::default());
+        let outcome = CompiledProgram::new(code, &config).unwrap().run(&mut ctx);
+        assert_eq!(
+            (outcome.status, outcome.gas_used),
+            (status, vm.gas_used()),
+            "{code:02x?}"
+        );
+        assert_eq!(ctx.host().stack(), vm.stack(), "{code:02x?}");
+        assert_eq!(ctx.memory(), vm.memory(), "{code:02x?}");
+        assert_eq!(ctx.storage()[0], vm.storage().get(&0).copied().unwrap_or(0));
+    }
+    assert_eq!(vm.stack(), [9, 9, 1]);
+}
+
+/// Pushing past the config's stack limit halts with `StackOverflow` and
+/// charges the whole gas limit.
+#[test]
+fn stack_limit() {
+    let config = |stack_limit, underflow| Config {
+        stack_limit,
+        underflow,
+    };
+    for underflow in [UnderflowPolicy::ZeroFill, UnderflowPolicy::Trap] {
+        let limit_2 = config(2, underflow);
+        let last = assert_same(&[PUSH, 1, PUSH, 2, STOP], &[], 1_000, limit_2);
+        assert_eq!(last.status, Status::Stopped);
+        for code in [
+            &[PUSH, 1, PUSH, 2, PUSH, 3, STOP][..],
+            &[PUSH, 1, PUSH, 2, DUP1, STOP],
+            &[PUSH, 1, PUSH, 2, CALLER, STOP],
+        ] {
+            let last = assert_same(code, &[], 1_000, limit_2);
+            assert_eq!((last.status, last.gas_used), (Status::StackOverflow, 1_000));
+        }
+        // replacing the top item does not grow the stack
+        let last = assert_same(&[PUSH, 1, PUSH, 2, NOT, STOP], &[], 1_000, limit_2);
+        assert_eq!(last.status, Status::Stopped);
+
+        let last = assert_same(&[PUSH, 1, STOP], &[], 1_000, config(0, underflow));
+        assert_eq!((last.status, last.gas_used), (Status::StackOverflow, 1_000));
+    }
+    // with no room at all, even an ADD of zero-filled operands overflows
+    let last = assert_same(
+        &[ADD, STOP],
+        &[],
+        1_000,
+        config(0, UnderflowPolicy::ZeroFill),
+    );
+    assert_eq!((last.status, last.gas_used), (Status::StackOverflow, 1_000));
+
+    // each run of a reused engine starts below the limit
+    let limit_2 = config(2, UnderflowPolicy::ZeroFill);
+    let mut vm = VM::default();
+    vm.set_config(limit_2);
+    vm.set_gas_limit(1_000);
+    let program = CompiledProgram::new(&[PUSH, 1, STOP], &limit_2).unwrap();
+    let mut ctx = Context::new(limit_2);
+    ctx.gas_limit = 1_000;
+    for _ in 0..3 {
+        let status = vm.interpret(&[PUSH, 1, STOP], &[], &Environment::default());
+        assert_eq!(status, Status::Stopped);
+        assert_eq!(program.run(&mut ctx).status, Status::Stopped);
+    }
+}
+
+/// Code calling program `id` with `gas`, no input and no output, leaving
+/// the success flag.
+fn call(id: u8, gas: u64) -> Vec<u8> {
+    [
+        &[PUSH, 0, PUSH, 0, PUSH, 0, PUSH, 0, PUSH, id][..],
+        &push(gas),
+        &[CALL],
+    ]
+    .concat()
+}
+
+#[test]
+fn deploy_validates() {
+    for code in [&[PUSH, 1, 0xEE, STOP][..], &[PUSH]] {
+        assert!(VM::default().deploy(code.to_vec()).is_err());
+        assert!(
+            Context::new(Config::default())
+                .host_mut()
+                .deploy(code)
+                .is_err()
+        );
+    }
+}
+
+#[test]
+fn call_isolates_storage() {
+    // the callee sees its own slot 0, not the caller's
+    let callee: &[u8] = &[SLOAD, 0, PUSH, 5, ADD, SSTORE, 0, STOP];
+    let code = [
+        &[PUSH, 1, SSTORE, 0][..],
+        &call(0, 1_000),
+        &[SLOAD, 0, STOP],
+    ]
+    .concat();
+    let last = assert_same(&code, &[callee], 10_000, Config::default());
+    assert_eq!(last.status, Status::Stopped);
+    assert_eq!(last.stack, [1, 1]);
+    assert_eq!(last.storage, [(0, 1)]);
+    assert_eq!(last.deployed, [vec![(0, 5)]]);
+}
+
+#[test]
+fn failed_call_rolls_back_nested_calls() {
+    let inner: &[u8] = &[PUSH, 8, SSTORE, 0, STOP];
+    // writes, calls `inner`, which succeeds, then fails on an empty return
+    // stack
+    let failing = [&[PUSH, 7, SSTORE, 0][..], &call(1, 1_000), &[POP, RETSUB]].concat();
+    let succeeding = [&[PUSH, 7, SSTORE, 1][..], &call(1, 1_000), &[POP, STOP]].concat();
+    let deployed = [&failing[..], inner, &succeeding];
+    let code = [call(0, 2_000), call(2, 2_000), vec![STOP]].concat();
+    let last = assert_same(&code, &deployed, 100_000, Config::default());
+    assert_eq!(last.status, Status::Stopped);
+    assert_eq!(last.stack, [0, 1]);
+    assert_eq!(last.deployed, [vec![], vec![(0, 8)], vec![(1, 7)]]);
+}
+
+#[test]
+fn failed_call_charges_its_gas_limit() {
+    let failing: &[u8] = &[PUSH, 1, SSTORE, 0, RETSUB];
+    let overhead = interpret(&call(0, 0), &[failing], 100_000, Config::default()).gas_used;
+    for gas in [0, 1, 50, 103, 104, 5_000] {
+        let last = assert_same(&call(0, gas), &[failing], 100_000, Config::default());
+        assert_eq!(
+            (last.status, last.gas_used),
+            (Status::Stopped, overhead + gas)
+        );
+        assert_eq!(last.stack, [0]);
+    }
+    let last = assert_same(&call(0, 200_000), &[failing], 100_000, Config::default());
+    assert_eq!((last.status, last.gas_used), (Status::OutOfGas, 100_000));
+}
+
+#[test]
+fn call_depth_limit() {
+    // counts its frames in its own slot 0 and calls itself with all its gas
+    let call_self = [PUSH, 0, PUSH, 0, PUSH, 0, PUSH, 0, PUSH, 0, GAS, CALL, STOP];
+    let recursive = [&[SLOAD, 0, PUSH, 1, ADD, SSTORE, 0][..], &call_self].concat();
+    let last = assert_same(&call_self, &[&recursive], 10_000_000, Config::default());
+    assert_eq!(last.status, Status::Stopped);
+    assert_eq!(last.stack, [1]);
+    assert_eq!(last.deployed, [vec![(0, CALL_DEPTH_LIMIT as u64)]]);
+}
+
+/// CALLSUB entering the subroutine at `target`, an offset into the code.
+fn callsub(target: usize) -> Vec<u8> {
+    [&[CALLSUB][..], &(target as u16).to_be_bytes()].concat()
+}
+
+#[test]
+fn nested_subroutines() {
+    // main at 0, a at 8, b at 15; a calls b
+    let code = [
+        &[PUSH, 1][..],
+        &callsub(8),
+        &[PUSH, 4, STOP],
+        &[BEGINSUB, PUSH, 2],
+        &callsub(15),
+        &[RETSUB],
+        &[BEGINSUB, PUSH, 3, RETSUB],
+    ]
+    .concat();
+    let last = assert_same(&code, &[], 1_000, Config::default());
+    assert_eq!(last.status, Status::Stopped);
+    assert_eq!(last.stack, [1, 2, 3, 4]);
+    // four PUSHes, two CALLSUBs with their BEGINSUBs, two RETSUBs
+    assert_eq!(last.gas_used, 4 * 3 + 2 * (10 + 1) + 2 * 5);
+}
+
+#[test]
+fn retsub_with_an_empty_return_stack() {
+    let last = assert_same(&[PUSH, 1, RETSUB], &[], 1_000, Config::default());
+    assert_eq!(
+        (last.status, last.gas_used),
+        (Status::ReturnStackUnderflow, 3 + 5)
+    );
+}
+
+#[test]
+fn return_stack_limit() {
+    // a chain of `n` subroutines, each calling the next
+    let chain = |n: usize| {
+        let mut code = [callsub(4), vec![STOP]].concat();
+        for i in 0..n {
+            code.push(BEGINSUB);
+            if i + 1 < n {
+                code.extend(callsub(code.len() + 4));
+            }
+            code.push(RETSUB);
+        }
+        code
+    };
+    let last = assert_same(
+        &chain(RETURN_STACK_LIMIT),
+        &[],
+        1_000_000,
+        Config::default(),
+    );
+    assert_eq!(last.status, Status::Stopped);
+    let last = assert_same(
+        &chain(RETURN_STACK_LIMIT + 1),
+        &[],
+        1_000_000,
+        Config::default(),
+    );
+    assert_eq!(
+        (last.status, last.gas_used),
+        (
+            Status::ReturnStackOverflow,
+            RETURN_STACK_LIMIT as u64 * (10 + 1) + 10
+        )
+    );
+
+    // a subroutine calling itself
+    let code = [callsub(4), vec![STOP, BEGINSUB], callsub(4), vec![RETSUB]].concat();
+    let last = assert_same(&code, &[], 1_000_000, Config::default());
+    assert_eq!(
+        (last.status, last.gas_used),
+        (
+            Status::ReturnStackOverflow,
+            RETURN_STACK_LIMIT as u64 * (10 + 1) + 10
+        )
+    );
+}
+
+#[test]
+fn invalid_jump() {
+    for (code, gas_used) in [
+        // into the middle of the main code
+        ([callsub(4), vec![STOP, PUSH, 1, STOP]].concat(), 10),
+        // past the end of the code
+        ([callsub(0xFFFF), vec![STOP]].concat(), 10),
+        // onto a PUSH immediate that reads as BEGINSUB
+        (
+            [vec![PUSH, BEGINSUB], callsub(1), vec![STOP]].concat(),
+            3 + 10,
+        ),
+    ] {
+        let last = assert_same(&code, &[], 1_000, Config::default());
+        assert_eq!(
+            (last.status, last.gas_used),
+            (Status::InvalidJump, gas_used)
+        );
+    }
+}
+
+#[test]
+fn walk_into_subroutine() {
+    let last = assert_same(&[PUSH, 1, BEGINSUB, RETSUB], &[], 1_000, Config::default());
+    assert_eq!(
+        (last.status, last.gas_used),
+        (Status::WalkIntoSubroutine, 3 + 1)
+    );
+}
+
+/// A subroutine shares its caller's stack, so its ADDs underflow after
+/// using up the caller's one item.
+#[test]
+fn underflow_in_a_subroutine() {
+    let code = [
+        &[PUSH, 1][..],
+        &callsub(6),
+        &[STOP],
+        &[BEGINSUB, ADD, PUSH, 2, ADD, RETSUB],
+    ]
+    .concat();
+    for underflow in [UnderflowPolicy::ZeroFill, UnderflowPolicy::Trap] {
+        let config = Config {
+            underflow,
+            ..Config::default()
+        };
+        let last = assert_same(&code, &[], 1_000, config);
+        match underflow {
+            UnderflowPolicy::ZeroFill => {
+                assert_eq!(last.status, Status::Stopped);
+                assert_eq!(last.stack, [3]);
+            }
+            UnderflowPolicy::Trap => {
+                assert_eq!(
+                    (last.status, last.gas_used),
+                    (Status::StackUnderflow, 1_000)
+                );
+            }
+        }
+    }
+}
+
+/// Code logging no data under `topic`.
+fn log(topic: u8) -> Vec<u8> {
+    vec![PUSH, topic, PUSH, 0, PUSH, 0, LOG0 + 1]
+}
+
+#[test]
+fn failed_runs_drop_their_logs() {
+    let topics = |last: &Final| -> Vec<u64> { last.logs.iter().map(|log| log.topics[0]).collect() };
+    let code = [log(1), vec![RETSUB]].concat();
+    let last = assert_same(&code, &[], 10_000, Config::default());
+    assert_eq!(last.status, Status::ReturnStackUnderflow);
+    assert_eq!(topics(&last), []);
+    // enough gas for the log but not the SSTORE after it
+    let code = [log(1), vec![PUSH, 1, SSTORE, 0, STOP]].concat();
+    let last = assert_same(&code, &[], 800, Config::default());
+    assert_eq!(last.status, Status::OutOfGas);
+    assert_eq!(topics(&last), []);
+
+    // a failed callee drops its logs and those of the calls it made
+    let inner = [log(3), vec![STOP]].concat();
+    let failing = [log(2), call(1, 5_000), vec![RETSUB]].concat();
+    let succeeding = [log(4), call(1, 5_000), vec![STOP]].concat();
+    let deployed = [&failing[..], &inner, &succeeding];
+    let code = [log(1), call(0, 5_000), call(2, 5_000), log(5), vec![STOP]].concat();
+    let last = assert_same(&code, &deployed, 100_000, Config::default());
+    assert_eq!(last.status, Status::Stopped);
+    assert_eq!(last.stack, [0, 1]);
+    assert_eq!(topics(&last), [1, 4, 3, 5]);
+}
+
+/// Program counting its calls in transient slot 0 and recording the count
+/// in slot 0 of its storage.
+const COUNTER: [u8; 11] = [TLOAD, 0, PUSH, 1, ADD, DUP, TSTORE, 0, SSTORE, 0, STOP];
+
+#[test]
+fn failed_call_rolls_back_transient_storage() {
+    // with 250 gas the counter runs out at its SSTORE, after its TSTORE
+    let code = [
+        call(0, 250),
+        call(0, 250),
+        call(0, 10_000),
+        call(0, 10_000),
+        vec![STOP],
+    ]
+    .concat();
+    let last = assert_same(&code, &[&COUNTER], 100_000, Config::default());
+    assert_eq!(last.status, Status::Stopped);
+    assert_eq!(last.stack, [0, 0, 1, 1]);
+    assert_eq!(last.deployed, [vec![(0, 2)]]);
+}
+
+/// Each top-level run starts with zeroed transient storage, the caller's
+/// and every deployed program's.
+#[test]
+fn transient_storage_is_cleared_between_runs() {
+    let config = Config::default();
+    let code = [&COUNTER[..10], &call(0, 10_000), &[STOP]].concat();
+    let mut vm = VM::default();
+    vm.set_gas_limit(100_000);
+    vm.deploy(COUNTER.to_vec()).unwrap();
+    let program = CompiledProgram::new(&code, &config).unwrap();
+    let mut ctx = Context::new(config);
+    ctx.gas_limit = 100_000;
+    ctx.host_mut().deploy(&COUNTER).unwrap();
+    for _ in 0..3 {
+        let status = vm.interpret(&code, &[], &Environment::default());
+        assert_eq!(status, Status::Stopped);
+        assert_eq!(program.run(&mut ctx).status, Status::Stopped);
+        assert_eq!(vm.storage()[&0], 1);
+        assert_eq!(vm.program_storage(0)[&0], 1);
+        assert_eq!(ctx.storage()[0], 1);
+        assert_eq!(ctx.host().storage(0)[0], 1);
+    }
+}
+
+/// Runs `code` in a container with `data` and the call data `calldata` on
+/// both engines, checks they stop in the same state and returns the stack
+/// and the first 32 bytes of memory.
+fn run_with_inputs(code: &[u8], data: &[u8], calldata: &[u8]) -> (Vec<u64>, Vec<u8>) {
+    let container = Container::parse(&Container::encode(&[code], data, MAX_STACK_HEIGHT)).unwrap();
+    let config = Config::default();
+    let mut vm = VM::default();
+    vm.set_gas_limit(1_000_000);
+    let status = vm.interpret_container(&container, calldata, &Environment::default());
+    assert_eq!(status, Status::Stopped, "{code:02x?}");
+
+    let mut ctx = Context::new(config);
+    ctx.gas_limit = 1_000_000;
+    ctx.calldata = calldata.to_vec();
+    let outcome = CompiledProgram::from_container(&container, &config).run(&mut ctx);
+    assert_eq!(
+        (outcome.status, outcome.gas_used),
+        (status, vm.gas_used()),
+        "{code:02x?}"
+    );
+    assert_eq!(ctx.host().stack(), vm.stack(), "{code:02x?}");
+    assert_eq!(ctx.memory(), vm.memory(), "{code:02x?}");
+    (vm.stack().to_vec(), vm.memory()[..32].to_vec())
+}
+
+/// Code filling the first 32 bytes of memory with 0xff.
+fn fill_memory() -> Vec<u8> {
+    [0, 8, 16, 24]
+        .into_iter()
+        .flat_map(|offset| [PUSH, 0, NOT, PUSH, offset, MSTORE])
+        .collect()
+}
+
+/// Reads past the end of the call data are zero-padded, however far past.
+#[test]
+fn calldata_past_the_end() {
+    let calldata: Vec<u8> = (1..=12).collect();
+    let load = |offset: u64| {
+        let code = [push(offset), vec![CALLDATALOAD, STOP]].concat();
+        run_with_inputs(&code, &[], &calldata).0[0]
+    };
+    assert_eq!(load(0), 0x0102_0304_0506_0708);
+    assert_eq!(load(4), 0x0506_0708_090a_0b0c);
+    assert_eq!(load(5), 0x0607_0809_0a0b_0c00);
+    assert_eq!(load(11), 0x0c00_0000_0000_0000);
+    for offset in [12, 13, 1 << 32, u64::MAX - 7, u64::MAX] {
+        assert_eq!(load(offset), 0, "offset {offset:x}");
+    }
+    let (stack, _) = run_with_inputs(&[CALLDATASIZE, STOP], &[], &calldata);
+    assert_eq!(stack, [12]);
+
+    // CALLDATACOPY zero-fills what lies past the end of the call data
+    let copy = |offset: u64, size: u64| {
+        let code = [
+            fill_memory(),
+            push(size),
+            push(offset),
+            vec![PUSH, 4, CALLDATACOPY, STOP],
+        ]
+        .concat();
+        run_with_inputs(&code, &[], &calldata).1
+    };
+    let mut expected = vec![0xff; 32];
+    expected[4..12].copy_from_slice(&[9, 10, 11, 12, 0, 0, 0, 0]);
+    assert_eq!(copy(8, 8), expected);
+    for offset in [12, 1 << 32, u64::MAX - 3, u64::MAX] {
+        let mut expected = vec![0xff; 32];
+        expected[4..20].fill(0);
+        assert_eq!(copy(offset, 16), expected, "offset {offset:x}");
+    }
+    assert_eq!(copy(0, 0), vec![0xff; 32]);
+    // a size past the end of memory copies, then zero-fills, up to it
+    let mut expected = vec![0xff; 32];
+    expected[4..16].copy_from_slice(&calldata);
+    expected[16..].fill(0);
+    let code = [
+        fill_memory(),
+        push(u64::MAX),
+        vec![PUSH, 0, PUSH, 4, CALLDATACOPY, STOP],
+    ]
+    .concat();
+    assert_eq!(run_with_inputs(&code, &[], &calldata).1, expected);
+}
+
+/// Reads of the data section, which are zero-padded past its end like call
+/// data; DATALOADN's offsets are checked when the container is parsed.
+#[test]
+fn data_section() {
+    let data: Vec<u8> = (1..=12).collect();
+    let load = |offset: u64| {
+        let code = [push(offset), vec![DATALOAD, STOP]].concat();
+        run_with_inputs(&code, &data, &[]).0[0]
+    };
+    assert_eq!(load(0), 0x0102_0304_0506_0708);
+    assert_eq!(load(4), 0x0506_0708_090a_0b0c);
+    assert_eq!(load(5), 0x0607_0809_0a0b_0c00);
+    for offset in [12, 1 << 32, u64::MAX - 7, u64::MAX] {
+        assert_eq!(load(offset), 0, "offset {offset:x}");
+    }
+
+    let (stack, _) = run_with_inputs(&[DATALOADN, 0, 4, DATASIZE, STOP], &data, &[]);
+    assert_eq!(stack, [0x0506_0708_090a_0b0c, 12]);
+    for offset in [5u16, 12, u16::MAX] {
+        let [hi, lo] = offset.to_be_bytes();
+        let code = [DATALOADN, hi, lo, STOP];
+        let bytes = Container::encode(&[&code], &data, MAX_STACK_HEIGHT);
+        assert_eq!(
+            Container::parse(&bytes).unwrap_err(),
+            ContainerError::InvalidDataOffset {
+                pc: 0,
+                offset: offset as usize
+            }
+        );
+    }
+
+    let copy = |offset: u64, size: u64| {
+        let code = [
+            fill_memory(),
+            push(size),
+            push(offset),
+            vec![PUSH, 4, DATACOPY, STOP],
+        ]
+        .concat();
+        run_with_inputs(&code, &data, &[]).1
+    };
+    let mut expected = vec![0xff; 32];
+    expected[4..12].copy_from_slice(&data[2..10]);
+    assert_eq!(copy(2, 8), expected);
+    expected[4..14].copy_from_slice(&[7, 8, 9, 10, 11, 12, 0, 0, 0, 0]);
+    assert_eq!(copy(6, 10), expected);
+    for offset in [12, 1 << 32, u64::MAX] {
+        let mut expected = vec![0xff; 32];
+        expected[4..20].fill(0);
+        assert_eq!(copy(offset, 16), expected, "offset {offset:x}");
+    }
+}
+
+/// DUP16 and SWAP16 reaching the bottom of a stack exactly as deep as they
+/// need, and one item short of it under `Trap`.
+#[test]
+fn dup16_swap16_and_pop() {
+    let pushes = |n: u8| -> Vec<u8> { (1..=n).flat_map(|value| [PUSH, value]).collect() };
+    let trap = Config {
+        underflow: UnderflowPolicy::Trap,
+        ..Config::default()
+    };
+    for config in [Config::default(), trap] {
+        let last = assert_same(
+            &[pushes(16), vec![DUP16, STOP]].concat(),
+            &[],
+            1_000,
+            config,
+        );
+        assert_eq!(last.status, Status::Stopped);
+        let mut expected: Vec<u64> = (1..=16).collect();
+        expected.push(1);
+        assert_eq!(last.stack, expected);
+
+        let last = assert_same(
+            &[pushes(17), vec![SWAP16, STOP]].concat(),
+            &[],
+            1_000,
+            config,
+        );
+        assert_eq!(last.status, Status::Stopped);
+        let mut expected: Vec<u64> = (1..=17).collect();
+        expected.swap(0, 16);
+        assert_eq!(last.stack, expected);
+
+        let last = assert_same(&[PUSH, 1, PUSH, 2, PUSH, 3, POP, STOP], &[], 1_000, config);
+        assert_eq!((last.status, last.gas_used), (Status::Stopped, 3 * 3 + 2));
+        assert_eq!(last.stack, [1, 2]);
+    }
+    for code in [
+        [pushes(15), vec![DUP16, STOP]].concat(),
+        [pushes(16), vec![SWAP16, STOP]].concat(),
+        vec![POP, STOP],
+    ] {
+        let last = assert_same(&code, &[], 1_000, trap);
+        assert_eq!(last.status, Status::StackUnderflow, "{code:02x?}");
+    }
+}
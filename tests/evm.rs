@@ -0,0 +1,169 @@
+//! The EVM fixtures, decoded and run on both engines, compute what they
+//! would on the EVM.
+
+use poc_jit::{CompiledProgram, Config, Context, Environment, Status, VM, evm};
+
+/// Reads `fixtures/evm/<name>.hex`, ignoring whitespace and `#` comments.
+fn fixture(name: &str) -> Vec<u8> {
+    let path = format!("{}/fixtures/evm/{name}.hex", env!("CARGO_MANIFEST_DIR"));
+    let digits: String = std::fs::read_to_string(path)
+        .unwrap()
+        .lines()
+        .map(|line| line.split('#').next().unwrap())
+        .flat_map(|line| line.chars().filter(|c| !c.is_whitespace()))
+        .collect();
+    (0..digits.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(&digits[i..i + 2], 16).unwrap())
+        .collect()
+}
+
+/// A 32-byte big-endian ABI word.
+fn word(value: u64) -> [u8; 32] {
+    let mut word = [0; 32];
+    word[24..].copy_from_slice(&value.to_be_bytes());
+    word
+}
+
+/// Runs `code` on the interpreter and on the JIT, checks both stop in the
+/// same state and returns the interpreter's.
+fn run(code: &[u8], calldata: &[u8], storage: &[(u8, u64)], env: &Environment) -> VM {
+    let mut vm = VM::default();
+    vm.set_gas_limit(1_000_000);
+    vm.storage_mut().extend(storage.iter().copied());
+    assert_eq!(vm.interpret(code, calldata, env), Status::Stopped);
+
+    let config = Config::default();
+    let mut ctx = Context::new(config);
+    ctx.gas_limit = 1_000_000;
+    ctx.calldata = calldata.to_vec();
+    ctx.env = *env;
+    for &(key, value) in storage {
+        ctx.storage_mut()[key as usize] = value;
+    }
+    let outcome = CompiledProgram::new(code, &config).unwrap().run(&mut ctx);
+    assert_eq!(
+        (outcome.status, outcome.gas_used),
+        (Status::Stopped, vm.gas_used())
+    );
+    assert_eq!(ctx.host().stack(), vm.stack());
+    assert_eq!(ctx.memory(), vm.memory());
+    assert_eq!(ctx.host().logs(), vm.logs());
+    for key in 0..=u8::MAX {
+        let slot = vm.storage().get(&key).copied().unwrap_or(0);
+        assert_eq!(ctx.storage()[key as usize], slot, "slot {key}");
+    }
+    vm
+}
+
+#[test]
+fn token_transfer() {
+    let code = evm::decode(&fixture("token_transfer")).unwrap();
+    // transfer(to, amount)
+    let mut calldata = vec![0xa9, 0x05, 0x9c, 0xbb];
+    calldata.extend(word(0xbeef));
+    calldata.extend(word(300));
+    let env = Environment {
+        caller: 42,
+        ..Environment::default()
+    };
+    let vm = run(&code, &calldata, &[(0, 1000), (1, 5)], &env);
+
+    assert_eq!(vm.storage()[&0], 700);
+    assert_eq!(vm.storage()[&1], 305);
+    let [log] = vm.logs() else {
+        panic!("expected one log")
+    };
+    // topics are truncated to their low 8 bytes
+    assert_eq!(log.topics, [0x28f55a4df523b3ef, 42, 0xbeef]);
+    assert_eq!(log.data, word(300));
+}
+
+#[test]
+fn mul_div() {
+    let code = evm::decode(&fixture("mul_div")).unwrap();
+    let calldata = [word(6), word(35), word(7)].concat();
+    let vm = run(&code, &calldata, &[], &Environment::default());
+    assert_eq!(vm.storage()[&2], 30);
+    assert_eq!(vm.storage()[&3], 1024);
+}
+
+#[test]
+fn block_context() {
+    let code = evm::decode(&fixture("block_context")).unwrap();
+    let env = Environment {
+        caller: 42,
+        number: 17_000_000,
+        timestamp: 1_700_000_000,
+        chain_id: 5,
+        ..Environment::default()
+    };
+    let vm = run(&code, &[], &[], &env);
+    let slots: Vec<u64> = (0..4).map(|key| vm.storage()[&key]).collect();
+    assert_eq!(slots, [5, 42, 1_700_000_000, 17_000_000]);
+}
+
+#[test]
+fn free_memory_pointer() {
+    let code = evm::decode(&fixture("free_memory_pointer")).unwrap();
+    let vm = run(&code, &[], &[], &Environment::default());
+    let mut memory = [0; 0x60];
+    memory[0x40..].copy_from_slice(&word(0x80));
+    assert_eq!(vm.memory()[..0x60], memory);
+}
+
+#[test]
+fn memory_words() {
+    // mstore(0x20, 0x1122334455667788); mstore(0x1c, 0x99);
+    // sstore(0, mload(0x20)); sstore(1, mload(0x24))
+    let bytecode = [
+        &[
+            0x67, 0x11, 0x22, 0x33, 0x44, 0x55, 0x66, 0x77, 0x88, 0x60, 0x20, 0x52,
+        ][..],
+        &[0x60, 0x99, 0x60, 0x1c, 0x52],
+        &[0x60, 0x20, 0x51, 0x60, 0x00, 0x55],
+        &[0x60, 0x24, 0x51, 0x60, 0x01, 0x55],
+        &[0x00],
+    ]
+    .concat();
+    let vm = run(
+        &evm::decode(&bytecode).unwrap(),
+        &[],
+        &[],
+        &Environment::default(),
+    );
+    let mut memory = [0; 0x40];
+    memory[0x1c..0x3c].copy_from_slice(&word(0x99));
+    memory[0x3c..].copy_from_slice(&[0x55, 0x66, 0x77, 0x88]);
+    assert_eq!(vm.memory()[..0x40], memory);
+    assert_eq!(vm.storage()[&0], 0x9955667788);
+    assert_eq!(vm.storage()[&1], 0x5566778800000000);
+}
+
+#[test]
+fn byte_indexes_the_evm_word() {
+    // sstore(k, byte(i, 0x1122)) for i = 31, 30, 24, 23 and 0
+    let mut bytecode = Vec::new();
+    for (key, i) in [31, 30, 24, 23, 0].into_iter().enumerate() {
+        bytecode.extend([0x61, 0x11, 0x22, 0x60, i, 0x1a, 0x60, key as u8, 0x55]);
+    }
+    bytecode.push(0x00);
+    let vm = run(
+        &evm::decode(&bytecode).unwrap(),
+        &[],
+        &[],
+        &Environment::default(),
+    );
+    let slots: Vec<u64> = (0..5)
+        .map(|key| vm.storage().get(&key).copied().unwrap_or(0))
+        .collect();
+    assert_eq!(slots, [0x22, 0x11, 0, 0, 0]);
+}
+
+#[test]
+fn solc_dispatcher_branches() {
+    assert_eq!(
+        evm::decode(&fixture("solc_dispatcher")),
+        Err(evm::DecodeError::UnsupportedOpcode { pc: 10, op: 0x57 })
+    );
+}
@@ -0,0 +1,61 @@
+//! Builds `tests/ffi/test.c` against the static library and the checked-in
+//! header, and runs it.
+
+use std::path::PathBuf;
+use std::process::Command;
+
+/// The build script generates the header into `OUT_DIR` rather than the
+/// source tree; `include/poc_jit.h` must match it.
+#[test]
+fn header_up_to_date() {
+    let generated = concat!(env!("OUT_DIR"), "/poc_jit.h");
+    let checked_in = concat!(env!("CARGO_MANIFEST_DIR"), "/include/poc_jit.h");
+    assert!(
+        std::fs::read_to_string(generated).unwrap() == std::fs::read_to_string(checked_in).unwrap(),
+        "include/poc_jit.h is out of date; update it with\n  cp {generated} {checked_in}"
+    );
+}
+
+#[test]
+fn c_program() {
+    let root = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+    // tests run from target/<profile>/deps; cargo only builds the rlib for
+    // them, so the static library is built here, into the same directory
+    let exe = std::env::current_exe().unwrap();
+    let lib_dir = exe.parent().and_then(|deps| deps.parent()).unwrap();
+    let profile = match lib_dir.file_name().unwrap().to_str().unwrap() {
+        "debug" => "dev",
+        profile => profile,
+    };
+    let status = Command::new(env!("CARGO"))
+        .args(["build", "--lib", "--features", "ffi", "--profile", profile])
+        .arg("--manifest-path")
+        .arg(root.join("Cargo.toml"))
+        .arg("--target-dir")
+        .arg(lib_dir.parent().unwrap())
+        .status()
+        .expect("failed to run cargo");
+    assert!(status.success(), "building the static library failed");
+
+    let binary = PathBuf::from(env!("CARGO_TARGET_TMPDIR")).join("ffi_test");
+    let status = Command::new(std::env::var("CC").unwrap_or_else(|_| "cc".into()))
+        .arg("-std=c99")
+        .arg("-Wall")
+        .arg("-Werror")
+        .arg(root.join("tests/ffi/test.c"))
+        .arg("-I")
+        .arg(root.join("include"))
+        .arg(lib_dir.join("libpoc_jit.a"))
+        .args(["-lpthread", "-ldl", "-lm", "-o"])
+        .arg(&binary)
+        .status()
+        .expect("failed to run the C compiler");
+    assert!(status.success(), "compiling test.c failed");
+
+    let output = Command::new(&binary).output().unwrap();
+    assert!(
+        output.status.success(),
+        "test.c failed: {}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+}
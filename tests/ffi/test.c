@@ -0,0 +1,172 @@
+/* Drives the C API through both engines; exits non-zero on the first
+ * failed check. */
+
+#include <stdint.h>
+#include <stdio.h>
+#include <stdlib.h>
+#include <string.h>
+
+#include "poc_jit.h"
+
+#define CHECK(cond)                                                        \
+    do {                                                                   \
+        if (!(cond)) {                                                     \
+            fprintf(stderr, "%s:%d: check failed: %s\n", __FILE__,         \
+                    __LINE__, #cond);                                      \
+            exit(1);                                                       \
+        }                                                                  \
+    } while (0)
+
+struct storage {
+    uint64_t slots[256];
+    int loads;
+    int stores;
+};
+
+static uint64_t load(void *user_data, uint8_t key) {
+    struct storage *s = user_data;
+    s->loads++;
+    return s->slots[key];
+}
+
+static void store(void *user_data, uint8_t key, uint64_t value) {
+    struct storage *s = user_data;
+    s->stores++;
+    s->slots[key] = value;
+}
+
+/* slot 0 += 5, slot 1 = CALLER, returns slot 0 as a word */
+static const uint8_t CODE[] = {
+    0x01, 0x00,             /* SLOAD 0 */
+    0x03, 0x05,             /* PUSH 5 */
+    0x04,                   /* ADD */
+    0x0F,                   /* DUP */
+    0x02, 0x00,             /* SSTORE 0 */
+    0x03, 0x00,             /* PUSH 0 */
+    0x15,                   /* MSTORE */
+    0x50,                   /* CALLER */
+    0x02, 0x01,             /* SSTORE 1 */
+    0x01, 0x02,             /* SLOAD 2 */
+    0x02, 0x02,             /* SSTORE 2, unchanged */
+    0x03, 0x08,             /* PUSH 8 */
+    0x03, 0x00,             /* PUSH 0 */
+    0x29,                   /* RETURN */
+};
+
+/* one section of CODE, no data, as a container */
+static const uint8_t HEADER[] = {
+    0xEF, 0x00, 0x01,
+    0x01, 0x00, 0x01, 0x00, sizeof(CODE),
+    0x02, 0x00, 0x00,
+    0x03, 0x00, 0x04,
+    0x00,
+};
+
+/* runs `program` twice from slot 0 = 10 and returns the gas of a run */
+static uint64_t run(PocEngine *engine, const PocProgram *program) {
+    struct storage s;
+    memset(&s, 0, sizeof(s));
+    s.slots[0] = 10;
+    s.slots[2] = 7;
+    PocStorageCallbacks callbacks = {&s, load, store};
+    PocEnvironment env = {0};
+    env.caller = 42;
+    PocExecResult result;
+    uint64_t gas_used = 0;
+
+    for (int i = 1; i <= 2; i++) {
+        s.loads = s.stores = 0;
+        CHECK(poc_execute(engine, program, NULL, 0, 1000, &env, &callbacks, &result));
+        CHECK(result.status == POC_STATUS_STOPPED);
+        CHECK(result.gas_used > 0);
+        CHECK(i == 1 || result.gas_used == gas_used);
+        gas_used = result.gas_used;
+        CHECK(s.slots[0] == 10 + 5 * (uint64_t)i);
+        CHECK(s.slots[1] == 42);
+        CHECK(s.slots[2] == 7);
+        CHECK(s.loads == 3);
+        CHECK(s.stores == (i == 1 ? 2 : 1));
+
+        size_t len;
+        const uint8_t *data = poc_engine_return_data(engine, &len);
+        CHECK(len == 8);
+        CHECK(data[7] == 10 + 5 * i);
+    }
+
+    CHECK(poc_execute(engine, program, NULL, 0, 3, &env, &callbacks, &result));
+    CHECK(result.status == POC_STATUS_OUT_OF_GAS);
+    CHECK(result.gas_used == 3);
+    return gas_used;
+}
+
+int main(void) {
+    PocEngine *engine = poc_engine_new(NULL);
+    CHECK(engine != NULL);
+
+    /* an undefined opcode, a truncated immediate */
+    static const uint8_t UNDEFINED[] = {0x03, 0x01, 0xEE, 0xFF};
+    static const uint8_t TRUNCATED[] = {0x03, 0x01, 0x03};
+    CHECK(poc_program_load(UNDEFINED, sizeof(UNDEFINED)) == NULL);
+    CHECK(poc_program_load(TRUNCATED, sizeof(TRUNCATED)) == NULL);
+
+    size_t len = 1;
+    CHECK(poc_engine_return_data(NULL, &len) == NULL);
+    CHECK(len == 0);
+    CHECK(poc_engine_return_data(engine, NULL) == NULL);
+
+    PocProgram *program = poc_program_load(CODE, sizeof(CODE));
+    CHECK(program != NULL);
+    uint64_t interpreted = run(engine, program);
+    CHECK(poc_program_compile(engine, program));
+    uint64_t compiled = run(engine, program);
+    CHECK(interpreted == compiled);
+
+    /* a program compiled for one config does not run under another */
+    PocEngineConfig config = {16, true};
+    PocEngine *other = poc_engine_new(&config);
+    PocExecResult result;
+    CHECK(!poc_execute(other, program, NULL, 0, 1000, NULL, NULL, &result));
+    CHECK(!poc_execute(engine, NULL, NULL, 0, 1000, NULL, NULL, &result));
+    poc_engine_free(other);
+    poc_program_free(program);
+
+    /* stack limits that cannot be allocated are rejected */
+    PocEngineConfig huge = {SIZE_MAX, false};
+    CHECK(poc_engine_new(&huge) == NULL);
+    huge.stack_limit = 65537;
+    CHECK(poc_engine_new(&huge) == NULL);
+    huge.stack_limit = 65536;
+    other = poc_engine_new(&huge);
+    CHECK(other != NULL);
+    poc_engine_free(other);
+
+    /* every run of a reused engine starts on an empty stack, so PUSH 1
+     * never reaches a limit of 2 */
+    static const uint8_t PUSH_1[] = {0x03, 0x01, 0xFF};
+    PocEngineConfig small = {2, false};
+    other = poc_engine_new(&small);
+    program = poc_program_load(PUSH_1, sizeof(PUSH_1));
+    for (int compiled = 0; compiled < 2; compiled++) {
+        CHECK(!compiled || poc_program_compile(other, program));
+        for (int i = 0; i < 3; i++) {
+            CHECK(poc_execute(other, program, NULL, 0, 1000, NULL, NULL, &result));
+            CHECK(result.status == POC_STATUS_STOPPED);
+        }
+    }
+    poc_engine_free(other);
+    poc_program_free(program);
+
+    uint8_t container[sizeof(HEADER) + sizeof(CODE)];
+    memcpy(container, HEADER, sizeof(HEADER));
+    memcpy(container + sizeof(HEADER), CODE, sizeof(CODE));
+    CHECK(poc_program_load_container(container, sizeof(container) - 1) == NULL);
+    program = poc_program_load_container(container, sizeof(container));
+    CHECK(program != NULL);
+    CHECK(run(engine, program) == interpreted);
+    CHECK(poc_program_compile(engine, program));
+    CHECK(run(engine, program) == interpreted);
+    poc_program_free(program);
+
+    poc_engine_free(engine);
+    return 0;
+}
@@ -0,0 +1,45 @@
+//! Keccak-256 known answers, around the 136-byte rate in particular.
+
+use poc_jit::keccak::keccak256;
+
+fn hex(digest: [u8; 32]) -> String {
+    digest.iter().map(|byte| format!("{byte:02x}")).collect()
+}
+
+#[test]
+fn known_answers() {
+    for (input, digest) in [
+        (
+            &b""[..],
+            "c5d2460186f7233c927e7db2dcc703c0e500b653ca82273b7bfad8045d85a470",
+        ),
+        (
+            b"abc",
+            "4e03657aea45a94fc7d47ba826c8d667c0d1e6e33a64a036ec44f58fa12d6c45",
+        ),
+    ] {
+        assert_eq!(hex(keccak256(input)), digest, "{input:?}");
+    }
+}
+
+/// One byte short of a block, where both padding bits share a byte, a
+/// whole block, which pads into a block of its own, and one byte over.
+#[test]
+fn block_boundaries() {
+    for (len, digest) in [
+        (
+            135,
+            "34367dc248bbd832f4e3e69dfaac2f92638bd0bbd18f2912ba4ef454919cf446",
+        ),
+        (
+            136,
+            "a6c4d403279fe3e0af03729caada8374b5ca54d8065329a3ebcaeb4b60aa386e",
+        ),
+        (
+            137,
+            "d869f639c7046b4929fc92a4d988a8b22c55fbadb802c0c66ebcd484f1915f39",
+        ),
+    ] {
+        assert_eq!(hex(keccak256(&vec![b'a'; len])), digest, "{len} bytes of a");
+    }
+}
@@ -0,0 +1,43 @@
+//! Compiled code handed fewer storage slots than its keys need halts with
+//! `StorageTooSmall` before running anything.
+
+use poc_jit::host::Host;
+use poc_jit::instructions::opcodes::*;
+use poc_jit::jit::make_jit;
+use poc_jit::{Config, Environment, MEMORY_SIZE, Status};
+
+#[test]
+fn storage_too_small() {
+    // writes slot 0 and memory before reading slot 5
+    let code = [PUSH, 1, SSTORE, 0, PUSH, 9, PUSH, 0, MSTORE, SLOAD, 5, STOP];
+    let config = Config::default();
+    let (buf, map) = make_jit(&code, &config);
+    let mut host = Host::new(config);
+    for slots in [0, 1, 5, 6] {
+        let mut storage = vec![7; slots];
+        let mut memory = vec![0; MEMORY_SIZE];
+        let result = unsafe {
+            host.run_guarded(
+                &buf,
+                &map,
+                &mut storage,
+                &mut memory,
+                &[],
+                1_000_000,
+                &Environment::default(),
+            )
+        }
+        .unwrap();
+        if slots < 6 {
+            assert_eq!(result.status(), Status::StorageTooSmall, "{slots} slots");
+            assert_eq!(result.gas_used, 0);
+            assert_eq!(storage, vec![7; slots]);
+            assert!(memory.iter().all(|&byte| byte == 0));
+            assert_eq!(host.stack(), []);
+        } else {
+            assert_eq!(result.status(), Status::Stopped);
+            assert_eq!(storage, [1, 7, 7, 7, 7, 7]);
+            assert_eq!(host.stack(), [7]);
+        }
+    }
+}
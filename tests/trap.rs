@@ -0,0 +1,83 @@
+//! Faults in compiled code are returned as a `Trap` by `run_guarded`, located
+//! through the `CodeMap` of the native code.
+
+use poc_jit::host::Host;
+use poc_jit::instructions::{immediate_len, opcodes::*};
+use poc_jit::jit::make_jit;
+use poc_jit::{Config, Environment, MEMORY_SIZE, STORAGE_SLOTS, Status, Trap};
+
+/// Runs `code` with `memory_len` bytes of memory, where the JIT assumes
+/// `MEMORY_SIZE`.
+fn run(code: &[u8], memory_len: usize) -> Result<Status, Trap> {
+    let config = Config::default();
+    let (buf, map) = make_jit(code, &config);
+    let mut host = Host::new(config);
+    let mut storage = vec![0; STORAGE_SLOTS];
+    let mut memory = vec![0; memory_len];
+    let result = unsafe {
+        host.run_guarded(
+            &buf,
+            &map,
+            &mut storage,
+            &mut memory,
+            &[],
+            1_000_000,
+            &Environment::default(),
+        )
+    };
+    result.map(|result| result.status())
+}
+
+#[test]
+fn fault_in_compiled_code() {
+    // an empty memory buffer makes the MSTORE at pc 4 write to a dangling
+    // pointer
+    let code = [PUSH, 7, PUSH, 0, MSTORE, STOP];
+    let trap = Trap {
+        signal: libc::SIGSEGV,
+        program: None,
+        pc: 4,
+    };
+    assert_eq!(run(&code, 0), Err(trap));
+    // the handler is left ready for the next run
+    assert_eq!(run(&code, MEMORY_SIZE), Ok(Status::Stopped));
+    assert_eq!(run(&code, 0), Err(trap));
+}
+
+/// The code map lists every instruction once, in code order, and maps each
+/// byte of an instruction's native code back to its pc.
+#[test]
+fn code_map() {
+    // main code, then a subroutine at 6
+    let code = [
+        PUSH, 1, CALLSUB, 0, 6, STOP, BEGINSUB, DUP1, SLOAD, 3, ADD, SSTORE, 3, RETSUB,
+    ];
+    let (_, map) = make_jit(&code, &Config::default());
+    let mut pcs = Vec::new();
+    let mut pc = 0;
+    while pc < code.len() {
+        pcs.push(pc);
+        pc += 1 + immediate_len(code[pc]);
+    }
+    let mapped: Vec<usize> = map.pcs().iter().map(|&(pc, _)| pc).collect();
+    assert_eq!(mapped, pcs);
+    assert!(map.pcs().windows(2).all(|pair| pair[0].1 <= pair[1].1));
+
+    let instructions: Vec<_> = map.instructions().collect();
+    assert_eq!(instructions.len(), pcs.len());
+    for (&(pc, ref range), &(start_pc, start)) in instructions.iter().zip(map.pcs()) {
+        assert_eq!((pc, range.start), (start_pc, start));
+        assert!(!range.is_empty(), "pc {pc} has no native code");
+        for offset in range.clone() {
+            assert_eq!(map.pc_at(offset), Some(pc), "offset {offset}");
+        }
+    }
+    assert!(
+        instructions
+            .windows(2)
+            .all(|pair| pair[0].1.end == pair[1].1.start)
+    );
+    let (first, last) = (&instructions[0].1, &instructions[instructions.len() - 1].1);
+    assert_eq!(map.pc_at(first.start.wrapping_sub(1)), None);
+    assert_eq!(map.pc_at(last.end), None);
+}